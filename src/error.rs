@@ -0,0 +1,245 @@
+// Errors returned by parsers and builders
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+
+    Tun,
+    VirtioNet,
+    Ethernet,
+    Sll,
+    Pppoe,
+    Mpls,
+    Arp,
+    Ipv4,
+    Ipv6,
+    Tcp,
+
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+
+    // Buffer ends before the layer does, expected and actual are byte counts
+    Truncated { layer: Layer, offset: usize, expected: usize, actual: usize },
+
+    // Protocol or EtherType field names something we do not parse
+    UnsupportedProtocol { layer: Layer, offset: usize, expected: u32, actual: u32 },
+
+    // Length field is smaller than the layer allows, expected is the minimum
+    InvalidLength { layer: Layer, offset: usize, expected: usize, actual: usize },
+
+    ChecksumMismatch { layer: Layer, offset: usize, expected: u16, actual: u16 },
+
+    UnsupportedVersion { layer: Layer, offset: usize, expected: u8, actual: u8 },
+
+    // Bits that must be zero are set, actual holds them shifted down
+    ReservedBitsSet { layer: Layer, offset: usize, actual: u8 },
+
+    // Option length byte does not fit the option kind or the space left
+    InvalidOption { layer: Layer, offset: usize, kind: u8, length: usize },
+
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+
+    OptionsTooLong { layer: Layer, max: usize, actual: usize },
+    PacketTooLarge { max: usize, actual: usize },
+    ExceedsMtu { mtu: usize, actual: usize },
+
+    // Datagram needs fragmenting but has DF set
+    FragmentationNeeded { mtu: usize, actual: usize },
+
+    // TCP GSO super-packet longer than the MTU, it has to be cut with Packet::segment instead
+    SegmentationNeeded { mtu: usize, actual: usize },
+
+}
+
+// Fragments that cannot be put back together, the datagram they belong to is dropped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReassemblyError {
+
+    // Fragment overlaps data already held, offset and length in bytes of datagram data
+    Overlap { offset: usize, length: usize },
+
+    // Fragment disagrees with the datagram length set by the last fragment, or is not a multiple of 8
+    InvalidLength { expected: usize, actual: usize },
+
+    // Data would end past the largest possible datagram
+    PacketTooLarge { max: usize, actual: usize },
+
+    // Buffered fragment data would exceed the limit even after evicting other datagrams
+    MemoryExhausted { limit: usize, actual: usize },
+
+    // Reassembled datagram does not parse
+    Parse(ParseError),
+
+}
+
+// Text that is not an address in the expected notation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressParseError {
+
+    pub input: String,
+
+}
+
+impl ParseError {
+
+    pub fn layer(&self) -> Layer {
+
+        match *self {
+            ParseError::Truncated { layer, .. } => layer,
+            ParseError::UnsupportedProtocol { layer, .. } => layer,
+            ParseError::InvalidLength { layer, .. } => layer,
+            ParseError::ChecksumMismatch { layer, .. } => layer,
+            ParseError::UnsupportedVersion { layer, .. } => layer,
+            ParseError::ReservedBitsSet { layer, .. } => layer,
+            ParseError::InvalidOption { layer, .. } => layer,
+        }
+
+    }
+
+    pub fn offset(&self) -> usize {
+
+        match *self {
+            ParseError::Truncated { offset, .. } => offset,
+            ParseError::UnsupportedProtocol { offset, .. } => offset,
+            ParseError::InvalidLength { offset, .. } => offset,
+            ParseError::ChecksumMismatch { offset, .. } => offset,
+            ParseError::UnsupportedVersion { offset, .. } => offset,
+            ParseError::ReservedBitsSet { offset, .. } => offset,
+            ParseError::InvalidOption { offset, .. } => offset,
+        }
+
+    }
+
+    // Moves the offset from the start of a layer to the start of the whole buffer
+    pub(crate) fn shift(mut self, amount: usize) -> ParseError {
+
+        match self {
+            ParseError::Truncated { ref mut offset, .. } => *offset += amount,
+            ParseError::UnsupportedProtocol { ref mut offset, .. } => *offset += amount,
+            ParseError::InvalidLength { ref mut offset, .. } => *offset += amount,
+            ParseError::ChecksumMismatch { ref mut offset, .. } => *offset += amount,
+            ParseError::UnsupportedVersion { ref mut offset, .. } => *offset += amount,
+            ParseError::ReservedBitsSet { ref mut offset, .. } => *offset += amount,
+            ParseError::InvalidOption { ref mut offset, .. } => *offset += amount,
+        }
+
+        self
+
+    }
+
+}
+
+impl fmt::Display for Layer {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        let name = match self {
+            Layer::Tun => "TUN",
+            Layer::VirtioNet => "virtio-net",
+            Layer::Ethernet => "Ethernet",
+            Layer::Sll => "SLL",
+            Layer::Pppoe => "PPPoE",
+            Layer::Mpls => "MPLS",
+            Layer::Arp => "ARP",
+            Layer::Ipv4 => "IPv4",
+            Layer::Ipv6 => "IPv6",
+            Layer::Tcp => "TCP",
+        };
+
+        f.write_str(name)
+
+    }
+
+}
+
+impl fmt::Display for ParseError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            ParseError::Truncated { layer, offset, expected, actual } =>
+                write!(f, "{} truncated at byte {}: expected {} bytes, got {}", layer, offset, expected, actual),
+            ParseError::UnsupportedProtocol { layer, offset, expected, actual } =>
+                write!(f, "{} unsupported protocol at byte {}: expected {:#x}, got {:#x}", layer, offset, expected, actual),
+            ParseError::InvalidLength { layer, offset, expected, actual } =>
+                write!(f, "{} invalid length at byte {}: expected at least {}, got {}", layer, offset, expected, actual),
+            ParseError::ChecksumMismatch { layer, offset, expected, actual } =>
+                write!(f, "{} checksum mismatch at byte {}: expected {:#06x}, got {:#06x}", layer, offset, expected, actual),
+            ParseError::UnsupportedVersion { layer, offset, expected, actual } =>
+                write!(f, "{} unsupported version at byte {}: expected {}, got {}", layer, offset, expected, actual),
+            ParseError::ReservedBitsSet { layer, offset, actual } =>
+                write!(f, "{} reserved bits set at byte {}: {:#b}", layer, offset, actual),
+            ParseError::InvalidOption { layer, offset, kind, length } =>
+                write!(f, "{} invalid option at byte {}: kind {} with length {}", layer, offset, kind, length),
+        }
+
+    }
+
+}
+
+impl fmt::Display for BuildError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            BuildError::OptionsTooLong { layer, max, actual } =>
+                write!(f, "{} options too long: at most {} bytes, got {}", layer, max, actual),
+            BuildError::PacketTooLarge { max, actual } =>
+                write!(f, "packet too large: at most {} bytes, got {}", max, actual),
+            BuildError::ExceedsMtu { mtu, actual } =>
+                write!(f, "packet exceeds MTU of {} bytes: got {}", mtu, actual),
+            BuildError::FragmentationNeeded { mtu, actual } =>
+                write!(f, "fragmentation needed for MTU of {} bytes but DF is set: got {}", mtu, actual),
+            BuildError::SegmentationNeeded { mtu, actual } =>
+                write!(f, "segmentation needed for MTU of {} bytes before fragmenting: got {}", mtu, actual),
+        }
+
+    }
+
+}
+
+impl fmt::Display for ReassemblyError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            ReassemblyError::Overlap { offset, length } =>
+                write!(f, "overlapping fragment: {} bytes at offset {}", length, offset),
+            ReassemblyError::InvalidLength { expected, actual } =>
+                write!(f, "invalid fragment length: expected {}, got {}", expected, actual),
+            ReassemblyError::PacketTooLarge { max, actual } =>
+                write!(f, "reassembled packet too large: at most {} bytes, got {}", max, actual),
+            ReassemblyError::MemoryExhausted { limit, actual } =>
+                write!(f, "reassembly memory exhausted: limit {} bytes, need {}", limit, actual),
+            ReassemblyError::Parse(error) =>
+                write!(f, "reassembled packet does not parse: {}", error),
+        }
+
+    }
+
+}
+
+impl fmt::Display for AddressParseError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        write!(f, "invalid address syntax: {:?}", self.input)
+
+    }
+
+}
+
+impl Error for ParseError {}
+
+impl Error for BuildError {}
+
+impl Error for ReassemblyError {}
+
+impl Error for AddressParseError {}
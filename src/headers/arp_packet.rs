@@ -0,0 +1,142 @@
+// Address Resolution Protocol for IPv4 over Ethernet
+// https://tools.ietf.org/html/rfc826
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |         Hardware Type         |         Protocol Type         |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |  HW Length    | Proto Length  |           Operation           |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                  Sender Hardware Address                      |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |  Sender HW Address (cont.)    |  Sender Protocol Address      |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |  Sender Proto Addr (cont.)    |  Target Hardware Address      |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                Target Hardware Address (cont.)                |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                   Target Protocol Address                     |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+use crate::error::{Layer, ParseError};
+use crate::headers::mac_address::MacAddress;
+
+pub const ARP_REQUEST: u16 = 1;
+pub const ARP_REPLY: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpPacket {
+
+    pub hardware_type: u16,                         // 1 for Ethernet
+    pub protocol_type: u16,                         // 0x0800 for IPv4
+    pub opcode: u16,
+    pub sender_hardware_address: MacAddress,
+    pub sender_protocol_address: u32,
+    pub target_hardware_address: MacAddress,
+    pub target_protocol_address: u32,
+
+}
+
+impl ArpPacket {
+
+    pub fn parse(bytes: &[u8]) -> Result<ArpPacket, ParseError> {
+
+        if bytes.len() < 28 {
+            // Cancel if packet is cut short
+            return Err(ParseError::Truncated { layer: Layer::Arp, offset: 0, expected: 28, actual: bytes.len() });
+        }
+
+        let hardware_type = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let protocol_type = u16::from_be_bytes([bytes[2], bytes[3]]);
+
+        if hardware_type != 1 {
+            // Not Ethernet
+            return Err(ParseError::UnsupportedProtocol { layer: Layer::Arp, offset: 0, expected: 1, actual: hardware_type as u32 });
+        }
+
+        if protocol_type != 0x0800 {
+            // Not IPv4
+            return Err(ParseError::UnsupportedProtocol { layer: Layer::Arp, offset: 2, expected: 0x0800, actual: protocol_type as u32 });
+        }
+
+        if bytes[4] != 6 {
+            return Err(ParseError::InvalidLength { layer: Layer::Arp, offset: 4, expected: 6, actual: bytes[4] as usize });
+        }
+
+        if bytes[5] != 4 {
+            return Err(ParseError::InvalidLength { layer: Layer::Arp, offset: 5, expected: 4, actual: bytes[5] as usize });
+        }
+
+        let sender_hardware_address = MacAddress::new([bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13]]);
+        let target_hardware_address = MacAddress::new([bytes[18], bytes[19], bytes[20], bytes[21], bytes[22], bytes[23]]);
+
+        Ok(
+            ArpPacket {
+                hardware_type,
+                protocol_type,
+                opcode: u16::from_be_bytes([bytes[6], bytes[7]]),
+                sender_hardware_address,
+                sender_protocol_address: u32::from_be_bytes([bytes[14], bytes[15], bytes[16], bytes[17]]),
+                target_hardware_address,
+                target_protocol_address: u32::from_be_bytes([bytes[24], bytes[25], bytes[26], bytes[27]]),
+            }
+        )
+
+    }
+
+    // Who has target_protocol_address? Tell sender_protocol_address
+    pub fn request(sender_hardware_address: MacAddress, sender_protocol_address: u32, target_protocol_address: u32) -> ArpPacket {
+
+        ArpPacket {
+            hardware_type: 1,
+            protocol_type: 0x0800,
+            opcode: ARP_REQUEST,
+            sender_hardware_address,
+            sender_protocol_address,
+            target_hardware_address: MacAddress::UNSPECIFIED,
+            target_protocol_address,
+        }
+
+    }
+
+    // Answer to a request for address, None if this is not such a request
+    pub fn reply(&self, address: u32, hardware_address: MacAddress) -> Option<ArpPacket> {
+
+        if self.opcode != ARP_REQUEST || self.target_protocol_address != address {
+            return None;
+        }
+
+        Some(
+            ArpPacket {
+                hardware_type: 1,
+                protocol_type: 0x0800,
+                opcode: ARP_REPLY,
+                sender_hardware_address: hardware_address,
+                sender_protocol_address: address,
+                target_hardware_address: self.sender_hardware_address,
+                target_protocol_address: self.sender_protocol_address,
+            }
+        )
+
+    }
+
+    pub fn get_bytes(&self) -> [u8; 28] {
+
+        let mut bytes = [0; 28];
+
+        bytes[0 .. 2].copy_from_slice(&self.hardware_type.to_be_bytes());
+        bytes[2 .. 4].copy_from_slice(&self.protocol_type.to_be_bytes());
+        bytes[4] = 6;
+        bytes[5] = 4;
+        bytes[6 .. 8].copy_from_slice(&self.opcode.to_be_bytes());
+        bytes[8 .. 14].copy_from_slice(&self.sender_hardware_address.octets());
+        bytes[14 .. 18].copy_from_slice(&self.sender_protocol_address.to_be_bytes());
+        bytes[18 .. 24].copy_from_slice(&self.target_hardware_address.octets());
+        bytes[24 .. 28].copy_from_slice(&self.target_protocol_address.to_be_bytes());
+
+        bytes
+
+    }
+
+}
@@ -0,0 +1,154 @@
+// Ethernet II header, as delivered by TAP devices without preamble or FCS
+// https://standards.ieee.org/standard/802_3-2018.html
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                      Destination Address                      |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |  Destination Address (cont.)  |         Source Address        |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                    Source Address (cont.)                     |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |           EtherType           |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// 802.1Q and 802.1ad tags sit between the source address and the EtherType
+
+use crate::error::{Layer, ParseError};
+use crate::headers::mac_address::MacAddress;
+use crate::headers::vlan_tag::VlanTag;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EthernetHeader {
+
+    pub destination_address: MacAddress,
+    pub source_address: MacAddress,
+    pub vlan_tags: Vec<VlanTag>,    // Outermost first
+    pub ether_type: u16,            // Of the payload, after all tags
+
+}
+
+#[derive(Debug, Clone)]
+pub struct EthernetHeaderBuilder {
+
+    pub destination_address: MacAddress,
+    pub source_address: MacAddress,
+    pub vlan_tags: Vec<VlanTag>,    // Outermost first
+
+}
+
+impl EthernetHeader {
+
+    pub fn parse(bytes: &[u8]) -> Result<EthernetHeader, ParseError> {
+
+        if bytes.len() < 14 {
+            // Cancel if header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Ethernet, offset: 0, expected: 14, actual: bytes.len() });
+        }
+
+        let destination_address = MacAddress::new([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]]);
+        let source_address = MacAddress::new([bytes[6], bytes[7], bytes[8], bytes[9], bytes[10], bytes[11]]);
+
+        let mut vlan_tags = Vec::new();
+        let mut offset = 12;
+        let mut ether_type = u16::from_be_bytes([bytes[offset], bytes[offset + 1]]);
+
+        while VlanTag::is_tpid(ether_type) {
+
+            if bytes.len() < offset + 6 {
+                // Cancel if tag or the EtherType behind it is cut short
+                return Err(ParseError::Truncated { layer: Layer::Ethernet, offset: 0, expected: offset + 6, actual: bytes.len() });
+            }
+
+            vlan_tags.push(VlanTag::parse(ether_type, [bytes[offset + 2], bytes[offset + 3]]));
+
+            offset += 4;
+            ether_type = u16::from_be_bytes([bytes[offset], bytes[offset + 1]]);
+
+        }
+
+        Ok(
+            EthernetHeader {
+                destination_address,
+                source_address,
+                vlan_tags,
+                ether_type,
+            }
+        )
+
+    }
+
+    pub fn get_length(&self) -> usize {
+
+        14 + self.vlan_tags.len() * 4
+
+    }
+
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+
+        bytes.extend_from_slice(&self.destination_address.octets());
+        bytes.extend_from_slice(&self.source_address.octets());
+
+        for vlan_tag in self.vlan_tags.iter() {
+            bytes.extend_from_slice(&vlan_tag.get_bytes());
+        }
+
+        bytes.extend_from_slice(&self.ether_type.to_be_bytes());
+
+    }
+
+}
+
+impl Default for EthernetHeaderBuilder {
+
+    fn default() -> EthernetHeaderBuilder {
+
+        EthernetHeaderBuilder::new()
+
+    }
+
+}
+
+impl EthernetHeaderBuilder {
+
+    pub fn new() -> EthernetHeaderBuilder {
+
+        EthernetHeaderBuilder {
+            destination_address: MacAddress::UNSPECIFIED,
+            source_address: MacAddress::UNSPECIFIED,
+            vlan_tags: Vec::new(),
+        }
+
+    }
+
+    // Adds an outer tag
+    pub fn push_vlan_tag(&mut self, vlan_tag: VlanTag) {
+
+        self.vlan_tags.insert(0, vlan_tag);
+
+    }
+
+    // Removes the outermost tag
+    pub fn pop_vlan_tag(&mut self) -> Option<VlanTag> {
+
+        if self.vlan_tags.is_empty() {
+            None
+        } else {
+            Some(self.vlan_tags.remove(0))
+        }
+
+    }
+
+    pub fn build(&self, ether_type: u16) -> EthernetHeader {
+
+        EthernetHeader {
+            destination_address: self.destination_address,
+            source_address: self.source_address,
+            vlan_tags: self.vlan_tags.clone(),
+            ether_type,
+        }
+
+    }
+
+}
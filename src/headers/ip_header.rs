@@ -17,9 +17,14 @@
 //  |                    Options                    |    Padding    |
 //  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
+use crate::error::{BuildError, Layer, ParseError};
+use crate::parse_options::ParseOptions;
+use crate::headers::ip_identification::Identification;
+use crate::headers::ip_option::{Ipv4Option, Ipv4OptionIter};
+use crate::headers::pseudo_header::PseudoHeader;
 use crate::headers::tcp_header::TCPHeader;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IPHeader {
 
     pub bytes: Vec<u8>,
@@ -27,6 +32,12 @@ pub struct IPHeader {
     pub version: u8,                // Should be 4
     pub total_length: u16,          // Whole packet length
     pub header_length: u8,
+    pub dscp: u8,
+    pub ecn: u8,
+    pub identification: u16,
+    pub dont_fragment: bool,
+    pub more_fragments: bool,
+    pub fragment_offset: u16,       // In 8 byte blocks
     pub ttl: u8,
     pub protocol: u8,
     pub header_checksum: u16,
@@ -36,53 +47,41 @@ pub struct IPHeader {
 
 }
 
+// Borrows the header from the caller's buffer and decodes fields on access
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4HeaderView<'a> {
+
+    bytes: &'a [u8],                // Header only, options included
+
+}
+
 #[derive(Debug)]
 pub struct IPHeaderBuilder {
 
     pub source_address: u32,
     pub destination_address: u32,
+    pub dscp: u8,
+    pub ecn: u8,
+    pub identification: Identification,
+    pub dont_fragment: bool,
+    pub more_fragments: bool,
+    pub fragment_offset: u16,       // In 8 byte blocks
     pub ttl: u8,
-    pub options: Vec<u8>,
+    pub options: Vec<Ipv4Option>,
 
 }
 
 impl IPHeader {
 
-    pub fn parse(bytes: &[u8]) -> Option<IPHeader> {
+    pub fn parse(bytes: &[u8]) -> Result<IPHeader, ParseError> {
 
-        let header_length = (bytes[0] & 0xF) * 4;
-
-        if header_length < 20 {
-            // Invalid header
-            return None;
-        }
-
-        let protocol = bytes[9];
+        IPHeader::parse_with(bytes, &ParseOptions::default())
 
-        if protocol != 6 {
-            // Not TCP
-            return None;
-        }
-
-        let ip_header = IPHeader {
-            bytes: bytes[.. header_length as usize].to_vec(),
-            version: (bytes[0] & 0xF0) >> 4,
-            header_length,
-            total_length: u16::from_be_bytes([bytes[2], bytes[3]]),
-            ttl: bytes[8],
-            protocol,
-            header_checksum: u16::from_be_bytes([bytes[10], bytes[11]]),
-            source_address: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
-            destination_address: u32::from_be_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
-            options: Vec::new(),
-        };
+    }
 
-        if ip_header.version == 4 && ip_header.header_length as u16 > ip_header.total_length {
-            // Invalid header
-            return None;
-        }
+    pub fn parse_with(bytes: &[u8], options: &ParseOptions) -> Result<IPHeader, ParseError> {
 
-        Some(ip_header)
+        Ok(Ipv4HeaderView::parse_with(bytes, options)?.to_owned())
 
     }
 
@@ -138,7 +137,15 @@ impl IPHeader {
 
     pub fn get_data_length(&self) -> u16 {
 
-        self.total_length - self.header_length as u16
+        // Unchecked total length may be shorter than the header
+        self.total_length.saturating_sub(self.header_length as u16)
+
+    }
+
+    // Part of a larger datagram, the upper layer header is not complete here
+    pub fn is_fragment(&self) -> bool {
+
+        self.more_fragments || self.fragment_offset != 0
 
     }
 
@@ -148,6 +155,293 @@ impl IPHeader {
 
     }
 
+    pub fn get_options(&self) -> Ipv4OptionIter<'_> {
+
+        Ipv4OptionIter::new(self.get_bytes())
+
+    }
+
+    pub fn get_pseudo_header(&self) -> PseudoHeader {
+
+        PseudoHeader::Ipv4 { source_address: self.source_address, destination_address: self.destination_address }
+
+    }
+
+    // Options repeated in every fragment but the first, padded to a whole word
+    pub fn get_copied_options(&self) -> Vec<u8> {
+
+        let copied: Vec<Ipv4Option> = self.get_options()
+            .map_while(Result::ok)
+            .filter(|option| option.is_copied())
+            .collect();
+
+        let mut options = Ipv4Option::encode_all(&copied);
+        options.resize(options.len().div_ceil(4) * 4, 0);
+
+        options
+
+    }
+
+    // Header of the fragment carrying data_length bytes from offset into this datagram's data.
+    // Offset is in bytes and must be a multiple of 8, only the first fragment keeps all options
+    pub fn get_fragment_header(&self, offset: usize, data_length: usize, more_fragments: bool) -> IPHeader {
+
+        let mut bytes = if offset == 0 {
+            self.get_bytes().to_vec()
+        } else {
+            let mut bytes = self.bytes[.. 20].to_vec();
+            bytes.extend_from_slice(&self.get_copied_options());
+            bytes
+        };
+
+        let header_length = bytes.len();
+        let total_length = (header_length + data_length) as u16;
+        let fragment_offset = (self.fragment_offset + (offset / 8) as u16) & 0x1FFF;
+        let more_fragments = more_fragments || self.more_fragments;
+
+        let mut flags: u8 = 0;
+        flags += (self.dont_fragment as u8) << 6;
+        flags += (more_fragments as u8) << 5;
+
+        bytes[0] = 0b01000000 + (header_length as u8 / 4);
+        bytes[2 .. 4].copy_from_slice(&total_length.to_be_bytes());
+        bytes[6] = flags | fragment_offset.to_be_bytes()[0];
+        bytes[7] = fragment_offset.to_be_bytes()[1];
+
+        let header_checksum = IPHeader::calculate_checksum(&bytes[..]);
+        bytes[10 .. 12].copy_from_slice(&header_checksum.to_be_bytes());
+
+        IPHeader {
+            options: bytes[20 ..].to_vec(),
+            bytes,
+            header_length: header_length as u8,
+            total_length,
+            more_fragments,
+            fragment_offset,
+            header_checksum,
+            ..self.clone()
+        }
+
+    }
+
+}
+
+impl<'a> Ipv4HeaderView<'a> {
+
+    pub fn parse(bytes: &'a [u8]) -> Result<Ipv4HeaderView<'a>, ParseError> {
+
+        Ipv4HeaderView::parse_with(bytes, &ParseOptions::default())
+
+    }
+
+    pub fn parse_with(bytes: &'a [u8], options: &ParseOptions) -> Result<Ipv4HeaderView<'a>, ParseError> {
+
+        if bytes.len() < 20 {
+            // Cancel if fixed part of header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Ipv4, offset: 0, expected: 20, actual: bytes.len() });
+        }
+
+        let version = (bytes[0] & 0xF0) >> 4;
+
+        if options.check_version && version != 4 {
+            // Not IPv4
+            return Err(ParseError::UnsupportedVersion { layer: Layer::Ipv4, offset: 0, expected: 4, actual: version });
+        }
+
+        let header_length = (bytes[0] & 0xF) as usize * 4;
+
+        if header_length < 20 {
+            // Invalid header
+            return Err(ParseError::InvalidLength { layer: Layer::Ipv4, offset: 0, expected: 20, actual: header_length });
+        }
+
+        if header_length > bytes.len() {
+            // Cancel if options are cut short
+            return Err(ParseError::Truncated { layer: Layer::Ipv4, offset: 0, expected: header_length, actual: bytes.len() });
+        }
+
+        let protocol = bytes[9];
+
+        if protocol != 6 {
+            // Not TCP
+            return Err(ParseError::UnsupportedProtocol { layer: Layer::Ipv4, offset: 9, expected: 6, actual: protocol as u32 });
+        }
+
+        let total_length = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+
+        if options.check_total_length && header_length > total_length {
+            // Invalid header
+            return Err(ParseError::InvalidLength { layer: Layer::Ipv4, offset: 2, expected: header_length, actual: total_length });
+        }
+
+        if options.check_reserved_bits && bytes[6] & 0b10000000 != 0 {
+            // Reserved flag must be zero
+            return Err(ParseError::ReservedBitsSet { layer: Layer::Ipv4, offset: 6, actual: 1 });
+        }
+
+        if options.verify_ip_checksum {
+
+            let header_checksum = u16::from_be_bytes([bytes[10], bytes[11]]);
+            let expected_checksum = IPHeader::calculate_checksum(&bytes[.. header_length]);
+
+            if header_checksum != expected_checksum {
+                // Cancel if invalid checksum
+                return Err(ParseError::ChecksumMismatch { layer: Layer::Ipv4, offset: 10, expected: expected_checksum, actual: header_checksum });
+            }
+
+        }
+
+        Ok(Ipv4HeaderView { bytes: &bytes[.. header_length] })
+
+    }
+
+    pub fn version(&self) -> u8 {
+
+        (self.bytes[0] & 0xF0) >> 4
+
+    }
+
+    pub fn header_length(&self) -> u8 {
+
+        (self.bytes[0] & 0xF) * 4
+
+    }
+
+    pub fn total_length(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[2], self.bytes[3]])
+
+    }
+
+    pub fn dscp(&self) -> u8 {
+
+        self.bytes[1] >> 2
+
+    }
+
+    pub fn ecn(&self) -> u8 {
+
+        self.bytes[1] & 0b11
+
+    }
+
+    pub fn identification(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[4], self.bytes[5]])
+
+    }
+
+    pub fn dont_fragment(&self) -> bool {
+
+        (self.bytes[6] & 0b01000000) >> 6 == 1
+
+    }
+
+    pub fn more_fragments(&self) -> bool {
+
+        (self.bytes[6] & 0b00100000) >> 5 == 1
+
+    }
+
+    pub fn fragment_offset(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[6] & 0b00011111, self.bytes[7]])
+
+    }
+
+    pub fn ttl(&self) -> u8 {
+
+        self.bytes[8]
+
+    }
+
+    pub fn protocol(&self) -> u8 {
+
+        self.bytes[9]
+
+    }
+
+    pub fn header_checksum(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[10], self.bytes[11]])
+
+    }
+
+    pub fn source_address(&self) -> u32 {
+
+        u32::from_be_bytes([self.bytes[12], self.bytes[13], self.bytes[14], self.bytes[15]])
+
+    }
+
+    pub fn destination_address(&self) -> u32 {
+
+        u32::from_be_bytes([self.bytes[16], self.bytes[17], self.bytes[18], self.bytes[19]])
+
+    }
+
+    pub fn get_data_length(&self) -> u16 {
+
+        self.total_length().saturating_sub(self.header_length() as u16)
+
+    }
+
+    pub fn is_fragment(&self) -> bool {
+
+        self.more_fragments() || self.fragment_offset() != 0
+
+    }
+
+    pub fn get_bytes(&self) -> &'a [u8] {
+
+        self.bytes
+
+    }
+
+    pub fn get_options(&self) -> Ipv4OptionIter<'a> {
+
+        Ipv4OptionIter::new(self.bytes)
+
+    }
+
+    pub fn get_pseudo_header(&self) -> PseudoHeader {
+
+        PseudoHeader::Ipv4 { source_address: self.source_address(), destination_address: self.destination_address() }
+
+    }
+
+    pub fn to_owned(self) -> IPHeader {
+
+        IPHeader {
+            bytes: self.bytes.to_vec(),
+            version: self.version(),
+            header_length: self.header_length(),
+            total_length: self.total_length(),
+            dscp: self.dscp(),
+            ecn: self.ecn(),
+            identification: self.identification(),
+            dont_fragment: self.dont_fragment(),
+            more_fragments: self.more_fragments(),
+            fragment_offset: self.fragment_offset(),
+            ttl: self.ttl(),
+            protocol: self.protocol(),
+            header_checksum: self.header_checksum(),
+            source_address: self.source_address(),
+            destination_address: self.destination_address(),
+            options: self.bytes[20 ..].to_vec(),
+        }
+
+    }
+
+}
+
+impl Default for IPHeaderBuilder {
+
+    fn default() -> IPHeaderBuilder {
+
+        IPHeaderBuilder::new()
+
+    }
+
 }
 
 impl IPHeaderBuilder {
@@ -157,51 +451,66 @@ impl IPHeaderBuilder {
         IPHeaderBuilder {
             source_address: 0,
             destination_address: 0,
+            dscp: 0,
+            ecn: 0,
+            identification: Identification::Fixed(0),
+            dont_fragment: false,
+            more_fragments: false,
+            fragment_offset: 0,
             ttl: 0,
             options: Vec::new(),
         }
 
     }
 
-    pub fn build(&self, tcp_header: &TCPHeader, data_length: usize) -> Option<IPHeader> {
+    pub fn get_pseudo_header(&self) -> PseudoHeader {
 
-        let options_length = self.options.len();
+        PseudoHeader::Ipv4 { source_address: self.source_address, destination_address: self.destination_address }
 
-        if options_length > 4 {
-            // Cancel if options too long
-            return None;
+    }
+
+    pub fn build(&self, tcp_header: &TCPHeader, data_length: usize) -> Result<IPHeader, BuildError> {
+
+        let options = Ipv4Option::encode_all(&self.options);
+        let options_length = options.len();
+
+        if options_length > 40 {
+            // Cancel if options do not fit the IHL field
+            return Err(BuildError::OptionsTooLong { layer: Layer::Ipv4, max: 40, actual: options_length });
         }
 
         let padding = (4 - options_length % 4) % 4;
         let header_length = 20 + options_length + padding;
         let total_length = header_length + data_length + tcp_header.data_offset as usize;
 
-        if total_length > 1500 || header_length > total_length {
-            // Cancel if packet too big
-            return None;
+        if total_length > 65535 {
+            // Cancel if packet too big for total length field
+            return Err(BuildError::PacketTooLarge { max: 65535, actual: total_length });
         }
 
+        let identification = self.identification.next(self.destination_address);
+
         let total_length_bytes = (total_length as u16).to_be_bytes();
+        let identification_bytes = identification.to_be_bytes();
         let source_address = self.source_address.to_be_bytes();
         let destination_address = self.destination_address.to_be_bytes();
 
+        let mut flags: u8 = 0;
+        flags += (self.dont_fragment as u8) << 6;
+        flags += (self.more_fragments as u8) << 5;
+
+        let fragment_offset = (self.fragment_offset & 0x1FFF).to_be_bytes();
+
         let mut bytes: Vec<u8> = vec!(
-            0b01000000 + (header_length as u8 / 4), 0, total_length_bytes[0], total_length_bytes[1],
-            0, 0, 0, 0,
+            0b01000000 + (header_length as u8 / 4), (self.dscp & 0x3F) << 2 | (self.ecn & 0b11), total_length_bytes[0], total_length_bytes[1],
+            identification_bytes[0], identification_bytes[1], flags | fragment_offset[0], fragment_offset[1],
             self.ttl, 6, 0, 0,
             source_address[0], source_address[1], source_address[2], source_address[3],
             destination_address[0], destination_address[1], destination_address[2], destination_address[3],
         );
 
-        for &option in self.options.iter() {
-            bytes.push(option);
-        }
-
-        if padding > 0 {
-            for _ in 0 .. padding {
-                bytes.push(0);
-            }
-        }
+        bytes.extend_from_slice(&options);
+        bytes.resize(header_length, 0);
 
         let header_checksum = IPHeader::calculate_checksum(&bytes[..]);
         let header_checksum_bytes = header_checksum.to_be_bytes();
@@ -209,18 +518,24 @@ impl IPHeaderBuilder {
         bytes[10] = header_checksum_bytes[0];
         bytes[11] = header_checksum_bytes[1];
 
-        Some(
+        Ok(
             IPHeader {
+                options: bytes[20 ..].to_vec(),
                 bytes,
                 version: 4,
                 header_length: header_length as u8,
                 total_length: total_length as u16,
+                dscp: self.dscp & 0x3F,
+                ecn: self.ecn & 0b11,
+                identification,
+                dont_fragment: self.dont_fragment,
+                more_fragments: self.more_fragments,
+                fragment_offset: self.fragment_offset & 0x1FFF,
                 header_checksum,
                 ttl: self.ttl,
                 protocol: 6,
                 source_address: self.source_address,
                 destination_address: self.destination_address,
-                options: self.options.clone(),
             }
         )
 
@@ -0,0 +1,74 @@
+// Identification field generators
+// https://tools.ietf.org/html/rfc6864
+
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Destinations tracked by PerDestination before an arbitrary one is forgotten and restarts from zero
+pub const PER_DESTINATION_LIMIT: usize = 4096;
+
+// Generators advance through a shared reference and are Sync, so builders holding them can be shared
+// between threads
+#[derive(Debug)]
+pub enum Identification {
+
+    Fixed(u16),
+    PerDestination(Mutex<HashMap<u32, u16>>),           // Next identification for each destination address, at most PER_DESTINATION_LIMIT
+    Random(AtomicU64),                                  // Xorshift state
+
+}
+
+impl Identification {
+
+    pub fn per_destination() -> Identification {
+
+        Identification::PerDestination(Mutex::new(HashMap::new()))
+
+    }
+
+    pub fn random() -> Identification {
+
+        // Seed from the randomly keyed std hasher, state must not be zero
+        let seed = RandomState::new().build_hasher().finish();
+
+        Identification::Random(AtomicU64::new(seed | 1))
+
+    }
+
+    pub fn next(&self, destination_address: u32) -> u16 {
+
+        match self {
+            Identification::Fixed(identification) => *identification,
+            Identification::PerDestination(counters) => {
+                // Counters stay valid if another thread panicked holding the lock
+                let mut counters = counters.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+                if counters.len() >= PER_DESTINATION_LIMIT && !counters.contains_key(&destination_address) {
+                    let forgotten = *counters.keys().next().unwrap();
+                    counters.remove(&forgotten);
+                }
+
+                let counter = counters.entry(destination_address).or_insert(0);
+                let identification = *counter;
+                *counter = counter.wrapping_add(1);
+                identification
+            },
+            Identification::Random(state) => {
+                let step = |mut x: u64| {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    x
+                };
+                // Always Ok, the closure never declines
+                let x = step(state.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| Some(step(x))).unwrap());
+                (x >> 48) as u16
+            },
+        }
+
+    }
+
+}
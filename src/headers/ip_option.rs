@@ -0,0 +1,223 @@
+// Internet Protocol options
+// https://tools.ietf.org/html/rfc791#section-3.1
+// https://tools.ietf.org/html/rfc1108
+// https://tools.ietf.org/html/rfc2113
+// https://www.iana.org/assignments/ip-parameters/ip-parameters.xhtml
+
+use crate::error::{Layer, ParseError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4Option {
+
+    EndOfList,                                                              // Type 0
+    Nop,                                                                    // Type 1
+    RecordRoute { pointer: u8, addresses: Vec<u32> },                       // Type 7
+    Timestamp { pointer: u8, overflow: u8, flag: u8, data: Vec<u32> },      // Type 68, data holds timestamps or address and timestamp pairs
+    LooseSourceRoute { pointer: u8, addresses: Vec<u32> },                  // Type 131
+    StrictSourceRoute { pointer: u8, addresses: Vec<u32> },                 // Type 137
+    RouterAlert(u16),                                                       // Type 148
+    Security { classification: u8, protection_authority: Vec<u8> },         // Type 130
+    Unknown { kind: u8, data: Vec<u8> },
+
+}
+
+// Decodes options one by one, stops after End of Option List or the first error
+pub struct Ipv4OptionIter<'a> {
+
+    bytes: &'a [u8],                // Whole IP header
+    offset: usize,                  // Next option, counted from header start
+    done: bool,
+
+}
+
+impl Ipv4Option {
+
+    pub fn kind(&self) -> u8 {
+
+        match self {
+            Ipv4Option::EndOfList => 0,
+            Ipv4Option::Nop => 1,
+            Ipv4Option::RecordRoute { .. } => 7,
+            Ipv4Option::Timestamp { .. } => 68,
+            Ipv4Option::LooseSourceRoute { .. } => 131,
+            Ipv4Option::StrictSourceRoute { .. } => 137,
+            Ipv4Option::RouterAlert(_) => 148,
+            Ipv4Option::Security { .. } => 130,
+            Ipv4Option::Unknown { kind, .. } => *kind,
+        }
+
+    }
+
+    // Copied flag, option must be repeated in every fragment
+    pub fn is_copied(&self) -> bool {
+
+        self.kind() & 0x80 != 0
+
+    }
+
+    pub fn get_length(&self) -> usize {
+
+        match self {
+            Ipv4Option::EndOfList | Ipv4Option::Nop => 1,
+            Ipv4Option::RecordRoute { addresses, .. } => 3 + addresses.len() * 4,
+            Ipv4Option::Timestamp { data, .. } => 4 + data.len() * 4,
+            Ipv4Option::LooseSourceRoute { addresses, .. } => 3 + addresses.len() * 4,
+            Ipv4Option::StrictSourceRoute { addresses, .. } => 3 + addresses.len() * 4,
+            Ipv4Option::RouterAlert(_) => 4,
+            Ipv4Option::Security { protection_authority, .. } => 3 + protection_authority.len(),
+            Ipv4Option::Unknown { data, .. } => 2 + data.len(),
+        }
+
+    }
+
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+
+        let kind = self.kind();
+        let length = self.get_length() as u8;
+
+        match self {
+            Ipv4Option::EndOfList | Ipv4Option::Nop => bytes.push(kind),
+            Ipv4Option::RecordRoute { pointer, addresses }
+            | Ipv4Option::LooseSourceRoute { pointer, addresses }
+            | Ipv4Option::StrictSourceRoute { pointer, addresses } => {
+                bytes.extend_from_slice(&[kind, length, *pointer]);
+                for address in addresses.iter() {
+                    bytes.extend_from_slice(&address.to_be_bytes());
+                }
+            },
+            Ipv4Option::Timestamp { pointer, overflow, flag, data } => {
+                bytes.extend_from_slice(&[kind, length, *pointer, (overflow << 4) | (flag & 0xF)]);
+                for word in data.iter() {
+                    bytes.extend_from_slice(&word.to_be_bytes());
+                }
+            },
+            Ipv4Option::RouterAlert(value) => {
+                bytes.extend_from_slice(&[kind, length]);
+                bytes.extend_from_slice(&value.to_be_bytes());
+            },
+            Ipv4Option::Security { classification, protection_authority } => {
+                bytes.extend_from_slice(&[kind, length, *classification]);
+                bytes.extend_from_slice(protection_authority);
+            },
+            Ipv4Option::Unknown { data, .. } => {
+                bytes.extend_from_slice(&[kind, length]);
+                bytes.extend_from_slice(data);
+            },
+        }
+
+    }
+
+    pub fn encode_all(options: &[Ipv4Option]) -> Vec<u8> {
+
+        let mut bytes = Vec::new();
+
+        for option in options.iter() {
+            option.write(&mut bytes);
+        }
+
+        bytes
+
+    }
+
+}
+
+impl<'a> Ipv4OptionIter<'a> {
+
+    pub(crate) fn new(header: &'a [u8]) -> Ipv4OptionIter<'a> {
+
+        Ipv4OptionIter {
+            bytes: header,
+            offset: 20,
+            done: false,
+        }
+
+    }
+
+    fn decode(&self) -> Result<(Ipv4Option, usize), ParseError> {
+
+        let bytes = &self.bytes[self.offset ..];
+        let kind = bytes[0];
+
+        match kind {
+            0 => return Ok((Ipv4Option::EndOfList, 1)),
+            1 => return Ok((Ipv4Option::Nop, 1)),
+            _ => (),
+        }
+
+        let invalid = |length: usize| ParseError::InvalidOption { layer: Layer::Ipv4, offset: self.offset, kind, length };
+
+        if bytes.len() < 2 {
+            // Length byte missing
+            return Err(invalid(bytes.len()));
+        }
+
+        let length = bytes[1] as usize;
+
+        if length < 2 || length > bytes.len() {
+            // Length does not cover type and length bytes or runs past the header
+            return Err(invalid(length));
+        }
+
+        let data = &bytes[2 .. length];
+        let words = |bytes: &[u8]| -> Vec<u32> {
+            bytes.chunks(4)
+                .map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+                .collect()
+        };
+
+        let option = match kind {
+            7 | 131 | 137 if length >= 3 && (length - 3) % 4 == 0 => {
+                let pointer = data[0];
+                let addresses = words(&data[1 ..]);
+                match kind {
+                    7 => Ipv4Option::RecordRoute { pointer, addresses },
+                    131 => Ipv4Option::LooseSourceRoute { pointer, addresses },
+                    _ => Ipv4Option::StrictSourceRoute { pointer, addresses },
+                }
+            },
+            68 if length >= 4 && length % 4 == 0 => Ipv4Option::Timestamp {
+                pointer: data[0],
+                overflow: data[1] >> 4,
+                flag: data[1] & 0xF,
+                data: words(&data[2 ..]),
+            },
+            148 if length == 4 => Ipv4Option::RouterAlert(u16::from_be_bytes([data[0], data[1]])),
+            130 if length >= 3 => Ipv4Option::Security {
+                classification: data[0],
+                protection_authority: data[1 ..].to_vec(),
+            },
+            7 | 68 | 130 | 131 | 137 | 148 => return Err(invalid(length)),
+            _ => Ipv4Option::Unknown { kind, data: data.to_vec() },
+        };
+
+        Ok((option, length))
+
+    }
+
+}
+
+impl<'a> Iterator for Ipv4OptionIter<'a> {
+
+    type Item = Result<Ipv4Option, ParseError>;
+
+    fn next(&mut self) -> Option<Result<Ipv4Option, ParseError>> {
+
+        if self.done || self.offset >= self.bytes.len() {
+            return None;
+        }
+
+        match self.decode() {
+            Ok((option, length)) => {
+                self.offset += length;
+                self.done = option == Ipv4Option::EndOfList;
+                Some(Ok(option))
+            },
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            },
+        }
+
+    }
+
+}
@@ -0,0 +1,410 @@
+// IPv6 extension headers
+// https://tools.ietf.org/html/rfc8200#section-4
+// https://tools.ietf.org/html/rfc8754#section-2
+// https://tools.ietf.org/html/rfc4302#section-2
+// https://tools.ietf.org/html/rfc4303#section-2
+// https://www.iana.org/assignments/ipv6-parameters/ipv6-parameters.xhtml
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |  Next Header  |  Hdr Ext Len  |                               |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+                               +
+//  |                  Type specific data, 8 byte units             |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// Fragment headers are always 8 bytes, AH counts its length in 4 byte units minus 2.
+// ESP has no cleartext next header, everything behind its SPI and sequence number is encrypted
+
+use std::net::Ipv6Addr;
+
+use crate::error::{Layer, ParseError};
+
+pub const IPV6_NEXT_HEADER_HOP_BY_HOP: u8 = 0;
+pub const IPV6_NEXT_HEADER_TCP: u8 = 6;
+pub const IPV6_NEXT_HEADER_ROUTING: u8 = 43;
+pub const IPV6_NEXT_HEADER_FRAGMENT: u8 = 44;
+pub const IPV6_NEXT_HEADER_ESP: u8 = 50;
+pub const IPV6_NEXT_HEADER_AUTHENTICATION: u8 = 51;
+pub const IPV6_NEXT_HEADER_NONE: u8 = 59;
+pub const IPV6_NEXT_HEADER_DESTINATION_OPTIONS: u8 = 60;
+
+pub const IPV6_ROUTING_TYPE_SEGMENT_ROUTING: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv6Extension {
+
+    HopByHop { options: Vec<u8> },                                          // Next header 0, options as on the wire, padding included
+    Routing { routing_type: u8, segments_left: u8, data: Vec<u8> },         // Next header 43, any routing type but segment routing
+    SegmentRouting {                                                        // Next header 43, routing type 4
+        segments_left: u8,
+        flags: u8,
+        tag: u16,
+        segments: Vec<Ipv6Addr>,                                            // Reverse order, final destination first
+        tlvs: Vec<u8>,
+    },
+    Fragment { fragment_offset: u16, more_fragments: bool, identification: u32 },   // Next header 44, offset in 8 byte blocks
+    DestinationOptions { options: Vec<u8> },                                // Next header 60
+    Authentication { spi: u32, sequence_number: u32, icv: Vec<u8> },        // Next header 51
+    EncapsulatingSecurityPayload { spi: u32, sequence_number: u32 },       // Next header 50, ends the chain
+
+}
+
+// Walks the chain behind the fixed header, stops at the upper-layer protocol, ESP or the first error
+pub struct Ipv6ExtensionIter<'a> {
+
+    bytes: &'a [u8],                // Fixed header and everything after it
+    offset: usize,                  // Next extension header, counted from header start
+    next_header: u8,                // Protocol found at offset
+    next_header_offset: usize,      // Where next_header was read from
+    done: bool,
+
+}
+
+impl Ipv6Extension {
+
+    pub fn is_extension(next_header: u8) -> bool {
+
+        matches!(
+            next_header,
+            IPV6_NEXT_HEADER_HOP_BY_HOP
+                | IPV6_NEXT_HEADER_ROUTING
+                | IPV6_NEXT_HEADER_FRAGMENT
+                | IPV6_NEXT_HEADER_ESP
+                | IPV6_NEXT_HEADER_AUTHENTICATION
+                | IPV6_NEXT_HEADER_DESTINATION_OPTIONS
+        )
+
+    }
+
+    // Next header value announcing this extension
+    pub fn kind(&self) -> u8 {
+
+        match self {
+            Ipv6Extension::HopByHop { .. } => IPV6_NEXT_HEADER_HOP_BY_HOP,
+            Ipv6Extension::Routing { .. } | Ipv6Extension::SegmentRouting { .. } => IPV6_NEXT_HEADER_ROUTING,
+            Ipv6Extension::Fragment { .. } => IPV6_NEXT_HEADER_FRAGMENT,
+            Ipv6Extension::DestinationOptions { .. } => IPV6_NEXT_HEADER_DESTINATION_OPTIONS,
+            Ipv6Extension::Authentication { .. } => IPV6_NEXT_HEADER_AUTHENTICATION,
+            Ipv6Extension::EncapsulatingSecurityPayload { .. } => IPV6_NEXT_HEADER_ESP,
+        }
+
+    }
+
+    // Length on the wire, padding included
+    pub fn get_length(&self) -> usize {
+
+        let padded = |length: usize| length.div_ceil(8) * 8;
+
+        match self {
+            Ipv6Extension::HopByHop { options } | Ipv6Extension::DestinationOptions { options } => padded(2 + options.len()),
+            Ipv6Extension::Routing { data, .. } => padded(4 + data.len()),
+            Ipv6Extension::SegmentRouting { segments, tlvs, .. } => padded(8 + segments.len() * 16 + tlvs.len()),
+            Ipv6Extension::Fragment { .. } => 8,
+            Ipv6Extension::Authentication { icv, .. } => padded(12 + icv.len()),
+            Ipv6Extension::EncapsulatingSecurityPayload { .. } => 8,
+        }
+
+    }
+
+    // Final destination named by a segment routing header that still has segments left
+    pub fn get_final_destination(&self) -> Option<Ipv6Addr> {
+
+        match self {
+            Ipv6Extension::SegmentRouting { segments_left, segments, .. } if *segments_left > 0 => segments.first().copied(),
+            _ => None,
+        }
+
+    }
+
+    pub fn write(&self, next_header: u8, bytes: &mut Vec<u8>) {
+
+        let start = bytes.len();
+        let length = self.get_length();
+
+        match self {
+            Ipv6Extension::HopByHop { options } | Ipv6Extension::DestinationOptions { options } => {
+                bytes.extend_from_slice(&[next_header, (length / 8 - 1) as u8]);
+                bytes.extend_from_slice(options);
+                // Pad1 for a single byte, PadN for more
+                match length - 2 - options.len() {
+                    0 => (),
+                    1 => bytes.push(0),
+                    padding => bytes.extend_from_slice(&[1, (padding - 2) as u8]),
+                }
+            },
+            Ipv6Extension::Routing { routing_type, segments_left, data } => {
+                bytes.extend_from_slice(&[next_header, (length / 8 - 1) as u8, *routing_type, *segments_left]);
+                bytes.extend_from_slice(data);
+            },
+            Ipv6Extension::SegmentRouting { segments_left, flags, tag, segments, tlvs } => {
+                let last_entry = segments.len().saturating_sub(1) as u8;
+                bytes.extend_from_slice(&[next_header, (length / 8 - 1) as u8, IPV6_ROUTING_TYPE_SEGMENT_ROUTING, *segments_left]);
+                bytes.extend_from_slice(&[last_entry, *flags]);
+                bytes.extend_from_slice(&tag.to_be_bytes());
+                for segment in segments.iter() {
+                    bytes.extend_from_slice(&segment.octets());
+                }
+                bytes.extend_from_slice(tlvs);
+            },
+            Ipv6Extension::Fragment { fragment_offset, more_fragments, identification } => {
+                let offset_flags = (fragment_offset & 0x1FFF) << 3 | *more_fragments as u16;
+                bytes.extend_from_slice(&[next_header, 0]);
+                bytes.extend_from_slice(&offset_flags.to_be_bytes());
+                bytes.extend_from_slice(&identification.to_be_bytes());
+            },
+            Ipv6Extension::Authentication { spi, sequence_number, icv } => {
+                bytes.extend_from_slice(&[next_header, (length / 4 - 2) as u8, 0, 0]);
+                bytes.extend_from_slice(&spi.to_be_bytes());
+                bytes.extend_from_slice(&sequence_number.to_be_bytes());
+                bytes.extend_from_slice(icv);
+            },
+            Ipv6Extension::EncapsulatingSecurityPayload { spi, sequence_number } => {
+                bytes.extend_from_slice(&spi.to_be_bytes());
+                bytes.extend_from_slice(&sequence_number.to_be_bytes());
+            },
+        }
+
+        bytes.resize(start + length, 0);
+
+    }
+
+    // Links the chain, the last extension announces upper_layer
+    pub fn encode_all(extensions: &[Ipv6Extension], upper_layer: u8) -> Vec<u8> {
+
+        let mut bytes = Vec::new();
+
+        for (index, extension) in extensions.iter().enumerate() {
+            let next_header = extensions.get(index + 1).map(|next| next.kind()).unwrap_or(upper_layer);
+            extension.write(next_header, &mut bytes);
+        }
+
+        bytes
+
+    }
+
+}
+
+impl<'a> Ipv6ExtensionIter<'a> {
+
+    pub(crate) fn new(header: &'a [u8]) -> Ipv6ExtensionIter<'a> {
+
+        Ipv6ExtensionIter {
+            bytes: header,
+            offset: 40,
+            next_header: header[6],
+            next_header_offset: 6,
+            done: false,
+        }
+
+    }
+
+    // Protocol after the extensions decoded so far, 50 once ESP hides the rest
+    pub fn next_header(&self) -> u8 {
+
+        self.next_header
+
+    }
+
+    // Length of the fixed header and the extensions decoded so far
+    pub fn offset(&self) -> usize {
+
+        self.offset
+
+    }
+
+    pub(crate) fn next_header_offset(&self) -> usize {
+
+        self.next_header_offset
+
+    }
+
+    // Next header and length of the extension at offset, checked without decoding its data
+    fn measure(&self) -> Result<(u8, usize), ParseError> {
+
+        let bytes = &self.bytes[self.offset ..];
+        let truncated = |expected: usize| ParseError::Truncated { layer: Layer::Ipv6, offset: self.offset, expected, actual: bytes.len() };
+
+        if bytes.len() < 8 {
+            // Cancel if extension header is cut short
+            return Err(truncated(8));
+        }
+
+        if self.next_header == IPV6_NEXT_HEADER_ESP {
+            return Ok((IPV6_NEXT_HEADER_ESP, 8));
+        }
+
+        let length = match self.next_header {
+            IPV6_NEXT_HEADER_FRAGMENT => 8,
+            IPV6_NEXT_HEADER_AUTHENTICATION => (bytes[1] as usize + 2) * 4,
+            _ => (bytes[1] as usize + 1) * 8,
+        };
+
+        if bytes.len() < length {
+            // Cancel if extension header data is cut short
+            return Err(truncated(length));
+        }
+
+        match self.next_header {
+            IPV6_NEXT_HEADER_ROUTING if bytes[2] == IPV6_ROUTING_TYPE_SEGMENT_ROUTING => {
+                let segment_count = bytes[4] as usize + 1;
+
+                if 8 + segment_count * 16 > length {
+                    // Last entry points past the header
+                    return Err(ParseError::InvalidLength { layer: Layer::Ipv6, offset: self.offset + 4, expected: 8 + segment_count * 16, actual: length });
+                }
+            },
+            IPV6_NEXT_HEADER_AUTHENTICATION if length < 12 => {
+                // Cancel if there is no room for SPI and sequence number
+                return Err(ParseError::InvalidLength { layer: Layer::Ipv6, offset: self.offset + 1, expected: 12, actual: length });
+            },
+            _ => (),
+        }
+
+        Ok((bytes[0], length))
+
+    }
+
+    fn decode(&self, length: usize) -> Ipv6Extension {
+
+        let bytes = &self.bytes[self.offset .. self.offset + length];
+        let word = |index: usize| u32::from_be_bytes([bytes[index], bytes[index + 1], bytes[index + 2], bytes[index + 3]]);
+        let data = &bytes[2 ..];
+
+        match self.next_header {
+            IPV6_NEXT_HEADER_ESP => Ipv6Extension::EncapsulatingSecurityPayload { spi: word(0), sequence_number: word(4) },
+            IPV6_NEXT_HEADER_HOP_BY_HOP => Ipv6Extension::HopByHop { options: data.to_vec() },
+            IPV6_NEXT_HEADER_DESTINATION_OPTIONS => Ipv6Extension::DestinationOptions { options: data.to_vec() },
+            IPV6_NEXT_HEADER_ROUTING if data[0] == IPV6_ROUTING_TYPE_SEGMENT_ROUTING => {
+                let segment_count = data[2] as usize + 1;
+
+                let segments = bytes[8 .. 8 + segment_count * 16]
+                    .chunks(16)
+                    .map(|segment| {
+                        let mut octets = [0; 16];
+                        octets.copy_from_slice(segment);
+                        Ipv6Addr::from(octets)
+                    })
+                    .collect();
+
+                Ipv6Extension::SegmentRouting {
+                    segments_left: data[1],
+                    flags: data[3],
+                    tag: u16::from_be_bytes([data[4], data[5]]),
+                    segments,
+                    tlvs: bytes[8 + segment_count * 16 ..].to_vec(),
+                }
+            },
+            IPV6_NEXT_HEADER_ROUTING => Ipv6Extension::Routing { routing_type: data[0], segments_left: data[1], data: data[2 ..].to_vec() },
+            IPV6_NEXT_HEADER_FRAGMENT => {
+                let offset_flags = u16::from_be_bytes([data[0], data[1]]);
+                Ipv6Extension::Fragment {
+                    fragment_offset: offset_flags >> 3,
+                    more_fragments: offset_flags & 0x1 == 1,
+                    identification: word(4),
+                }
+            },
+            _ => Ipv6Extension::Authentication { spi: word(4), sequence_number: word(8), icv: bytes[12 ..].to_vec() },
+        }
+
+    }
+
+    // Moves past a measured extension
+    fn advance(&mut self, next_header: u8, length: usize) {
+
+        let bytes = &self.bytes[self.offset ..];
+
+        match self.next_header {
+            // ESP keeps its own number, what follows it cannot be read
+            IPV6_NEXT_HEADER_ESP => self.done = true,
+            // Later fragments continue mid-datagram, the headers named there are in the first one
+            IPV6_NEXT_HEADER_FRAGMENT => self.done = u16::from_be_bytes([bytes[2], bytes[3]]) >> 3 != 0,
+            _ => (),
+        }
+
+        if self.next_header != IPV6_NEXT_HEADER_ESP {
+            self.next_header_offset = self.offset;
+            self.next_header = next_header;
+        }
+
+        self.offset += length;
+
+    }
+
+    // Checks the rest of the chain without decoding it. Collects what the upper layer needs on the way,
+    // the first final destination and whether the packet is a fragment
+    pub(crate) fn walk(&mut self) -> Result<Ipv6ChainSummary, ParseError> {
+
+        let mut summary = Ipv6ChainSummary { final_destination: None, is_fragment: false, is_later_fragment: false };
+
+        while !self.done && Ipv6Extension::is_extension(self.next_header) {
+
+            let (next_header, length) = match self.measure() {
+                Ok(measured) => measured,
+                Err(error) => {
+                    self.done = true;
+                    return Err(error);
+                },
+            };
+
+            let bytes = &self.bytes[self.offset ..];
+
+            match self.next_header {
+                IPV6_NEXT_HEADER_ROUTING if bytes[2] == IPV6_ROUTING_TYPE_SEGMENT_ROUTING && bytes[3] > 0 && summary.final_destination.is_none() => {
+                    let mut octets = [0; 16];
+                    octets.copy_from_slice(&bytes[8 .. 24]);
+                    summary.final_destination = Some(Ipv6Addr::from(octets));
+                },
+                IPV6_NEXT_HEADER_FRAGMENT => {
+                    let offset_flags = u16::from_be_bytes([bytes[2], bytes[3]]);
+                    summary.is_fragment |= offset_flags >> 3 != 0 || offset_flags & 0x1 == 1;
+                    summary.is_later_fragment = offset_flags >> 3 != 0;
+                },
+                _ => (),
+            }
+
+            self.advance(next_header, length);
+
+        }
+
+        Ok(summary)
+
+    }
+
+}
+
+// Found by Ipv6ExtensionIter::walk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Ipv6ChainSummary {
+
+    pub final_destination: Option<Ipv6Addr>,    // From a segment routing header with segments left
+    pub is_fragment: bool,
+    pub is_later_fragment: bool,                // Nonzero offset, the chain ends at the fragment header
+
+}
+
+impl<'a> Iterator for Ipv6ExtensionIter<'a> {
+
+    type Item = Result<Ipv6Extension, ParseError>;
+
+    fn next(&mut self) -> Option<Result<Ipv6Extension, ParseError>> {
+
+        if self.done || !Ipv6Extension::is_extension(self.next_header) {
+            return None;
+        }
+
+        match self.measure() {
+            Ok((next_header, length)) => {
+                let extension = self.decode(length);
+                self.advance(next_header, length);
+                Some(Ok(extension))
+            },
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            },
+        }
+
+    }
+
+}
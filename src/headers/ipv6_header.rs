@@ -0,0 +1,403 @@
+// Internet Protocol version 6 header
+// https://tools.ietf.org/html/rfc8200#section-3
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |Version| Traffic Class |           Flow Label                  |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |         Payload Length        |  Next Header  |   Hop Limit   |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                                                               |
+//  +                                                               +
+//  |                                                               |
+//  +                         Source Address                        +
+//  |                                                               |
+//  +                                                               +
+//  |                                                               |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                                                               |
+//  +                                                               +
+//  |                                                               |
+//  +                      Destination Address                      +
+//  |                                                               |
+//  +                                                               +
+//  |                                                               |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// Extension headers, if any, follow and are counted as part of this header
+
+use std::net::Ipv6Addr;
+
+use crate::error::{BuildError, Layer, ParseError};
+use crate::parse_options::ParseOptions;
+use crate::headers::ipv6_extension::{Ipv6Extension, Ipv6ExtensionIter, IPV6_NEXT_HEADER_TCP};
+use crate::headers::pseudo_header::PseudoHeader;
+use crate::headers::tcp_header::TCPHeader;
+
+#[derive(Debug, Clone)]
+pub struct Ipv6Header {
+
+    pub bytes: Vec<u8>,
+
+    pub version: u8,                // Should be 6
+    pub traffic_class: u8,
+    pub flow_label: u32,            // 20 bits
+    pub payload_length: u16,        // Everything after the fixed header
+    pub next_header: u8,            // First extension header or TCP
+    pub hop_limit: u8,
+    pub source_address: Ipv6Addr,
+    pub destination_address: Ipv6Addr,
+    pub extensions: Vec<Ipv6Extension>,
+
+}
+
+// Borrows the header from the caller's buffer and decodes fields on access
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv6HeaderView<'a> {
+
+    bytes: &'a [u8],                // Fixed header and extension headers
+    final_destination: Ipv6Addr,    // Found while walking the extension headers in parse_with
+    is_fragment: bool,
+
+}
+
+#[derive(Debug)]
+pub struct Ipv6HeaderBuilder {
+
+    pub source_address: Ipv6Addr,
+    pub destination_address: Ipv6Addr,
+    pub traffic_class: u8,
+    pub flow_label: u32,            // 20 bits
+    pub hop_limit: u8,
+    pub extensions: Vec<Ipv6Extension>,    // In wire order, next headers are linked up when building
+
+}
+
+// Destination the upper layer checksum is computed against, RFC 8200 section 8.1
+fn get_final_destination_address(destination_address: Ipv6Addr, extensions: &[Ipv6Extension]) -> Ipv6Addr {
+
+    extensions.iter()
+        .find_map(|extension| extension.get_final_destination())
+        .unwrap_or(destination_address)
+
+}
+
+// Part of a larger datagram, the upper layer header is not complete here
+fn is_fragment<'a>(mut extensions: impl Iterator<Item = &'a Ipv6Extension>) -> bool {
+
+    extensions.any(|extension| matches!(
+        extension,
+        Ipv6Extension::Fragment { fragment_offset, more_fragments, .. } if *more_fragments || *fragment_offset != 0
+    ))
+
+}
+
+impl Ipv6Header {
+
+    pub fn parse(bytes: &[u8]) -> Result<Ipv6Header, ParseError> {
+
+        Ipv6Header::parse_with(bytes, &ParseOptions::default())
+
+    }
+
+    pub fn parse_with(bytes: &[u8], options: &ParseOptions) -> Result<Ipv6Header, ParseError> {
+
+        Ok(Ipv6HeaderView::parse_with(bytes, options)?.to_owned())
+
+    }
+
+    // Fixed header and extension headers
+    pub fn get_length(&self) -> usize {
+
+        self.bytes.len()
+
+    }
+
+    // Upper layer data after the extension headers
+    pub fn get_data_length(&self) -> u16 {
+
+        self.payload_length.saturating_sub((self.bytes.len() - 40) as u16)
+
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
+
+        &self.bytes
+
+    }
+
+    pub fn get_extensions(&self) -> Ipv6ExtensionIter<'_> {
+
+        Ipv6ExtensionIter::new(&self.bytes)
+
+    }
+
+    pub fn get_final_destination_address(&self) -> Ipv6Addr {
+
+        get_final_destination_address(self.destination_address, &self.extensions)
+
+    }
+
+    pub fn get_pseudo_header(&self) -> PseudoHeader {
+
+        PseudoHeader::Ipv6 { source_address: self.source_address, destination_address: self.get_final_destination_address() }
+
+    }
+
+    pub fn is_fragment(&self) -> bool {
+
+        is_fragment(self.extensions.iter())
+
+    }
+
+}
+
+impl<'a> Ipv6HeaderView<'a> {
+
+    pub fn parse(bytes: &'a [u8]) -> Result<Ipv6HeaderView<'a>, ParseError> {
+
+        Ipv6HeaderView::parse_with(bytes, &ParseOptions::default())
+
+    }
+
+    pub fn parse_with(bytes: &'a [u8], options: &ParseOptions) -> Result<Ipv6HeaderView<'a>, ParseError> {
+
+        if bytes.len() < 40 {
+            // Cancel if header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Ipv6, offset: 0, expected: 40, actual: bytes.len() });
+        }
+
+        let version = (bytes[0] & 0xF0) >> 4;
+
+        if options.check_version && version != 6 {
+            // Not IPv6
+            return Err(ParseError::UnsupportedVersion { layer: Layer::Ipv6, offset: 0, expected: 6, actual: version });
+        }
+
+        // Walk the extension headers up to the upper layer
+        let mut extensions = Ipv6ExtensionIter::new(bytes);
+        let summary = extensions.walk()?;
+
+        let next_header = extensions.next_header();
+
+        // Later fragments name the first header of the fragmented part, the upper layer is only known after reassembly
+        if !summary.is_later_fragment && next_header != IPV6_NEXT_HEADER_TCP {
+            // Not TCP
+            return Err(
+                ParseError::UnsupportedProtocol {
+                    layer: Layer::Ipv6,
+                    offset: extensions.next_header_offset(),
+                    expected: 6,
+                    actual: next_header as u32,
+                }
+            );
+        }
+
+        let mut destination_address = [0; 16];
+        destination_address.copy_from_slice(&bytes[24 .. 40]);
+
+        Ok(
+            Ipv6HeaderView {
+                bytes: &bytes[.. extensions.offset()],
+                final_destination: summary.final_destination.unwrap_or_else(|| Ipv6Addr::from(destination_address)),
+                is_fragment: summary.is_fragment,
+            }
+        )
+
+    }
+
+    pub fn version(&self) -> u8 {
+
+        (self.bytes[0] & 0xF0) >> 4
+
+    }
+
+    pub fn traffic_class(&self) -> u8 {
+
+        (self.bytes[0] << 4) | (self.bytes[1] >> 4)
+
+    }
+
+    pub fn flow_label(&self) -> u32 {
+
+        u32::from_be_bytes([0, self.bytes[1] & 0x0F, self.bytes[2], self.bytes[3]])
+
+    }
+
+    pub fn payload_length(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[4], self.bytes[5]])
+
+    }
+
+    pub fn next_header(&self) -> u8 {
+
+        self.bytes[6]
+
+    }
+
+    pub fn hop_limit(&self) -> u8 {
+
+        self.bytes[7]
+
+    }
+
+    pub fn source_address(&self) -> Ipv6Addr {
+
+        let mut octets = [0; 16];
+        octets.copy_from_slice(&self.bytes[8 .. 24]);
+
+        Ipv6Addr::from(octets)
+
+    }
+
+    pub fn destination_address(&self) -> Ipv6Addr {
+
+        let mut octets = [0; 16];
+        octets.copy_from_slice(&self.bytes[24 .. 40]);
+
+        Ipv6Addr::from(octets)
+
+    }
+
+    pub fn get_length(&self) -> usize {
+
+        self.bytes.len()
+
+    }
+
+    pub fn get_data_length(&self) -> u16 {
+
+        self.payload_length().saturating_sub((self.bytes.len() - 40) as u16)
+
+    }
+
+    pub fn get_bytes(&self) -> &'a [u8] {
+
+        self.bytes
+
+    }
+
+    pub fn get_extensions(&self) -> Ipv6ExtensionIter<'a> {
+
+        Ipv6ExtensionIter::new(self.bytes)
+
+    }
+
+    pub fn get_final_destination_address(&self) -> Ipv6Addr {
+
+        self.final_destination
+
+    }
+
+    pub fn get_pseudo_header(&self) -> PseudoHeader {
+
+        PseudoHeader::Ipv6 { source_address: self.source_address(), destination_address: self.get_final_destination_address() }
+
+    }
+
+    pub fn is_fragment(&self) -> bool {
+
+        self.is_fragment
+
+    }
+
+    pub fn to_owned(self) -> Ipv6Header {
+
+        Ipv6Header {
+            bytes: self.bytes.to_vec(),
+            version: self.version(),
+            traffic_class: self.traffic_class(),
+            flow_label: self.flow_label(),
+            payload_length: self.payload_length(),
+            next_header: self.next_header(),
+            hop_limit: self.hop_limit(),
+            source_address: self.source_address(),
+            destination_address: self.destination_address(),
+            extensions: self.get_extensions().filter_map(Result::ok).collect(),
+        }
+
+    }
+
+}
+
+impl Default for Ipv6HeaderBuilder {
+
+    fn default() -> Ipv6HeaderBuilder {
+
+        Ipv6HeaderBuilder::new()
+
+    }
+
+}
+
+impl Ipv6HeaderBuilder {
+
+    pub fn new() -> Ipv6HeaderBuilder {
+
+        Ipv6HeaderBuilder {
+            source_address: Ipv6Addr::UNSPECIFIED,
+            destination_address: Ipv6Addr::UNSPECIFIED,
+            traffic_class: 0,
+            flow_label: 0,
+            hop_limit: 64,
+            extensions: Vec::new(),
+        }
+
+    }
+
+    pub fn get_final_destination_address(&self) -> Ipv6Addr {
+
+        get_final_destination_address(self.destination_address, &self.extensions)
+
+    }
+
+    pub fn get_pseudo_header(&self) -> PseudoHeader {
+
+        PseudoHeader::Ipv6 { source_address: self.source_address, destination_address: self.get_final_destination_address() }
+
+    }
+
+    pub fn build(&self, tcp_header: &TCPHeader, data_length: usize) -> Result<Ipv6Header, BuildError> {
+
+        let extensions = Ipv6Extension::encode_all(&self.extensions, IPV6_NEXT_HEADER_TCP);
+        let payload_length = extensions.len() + data_length + tcp_header.data_offset as usize;
+
+        if payload_length > 65535 {
+            // Cancel if payload too big for payload length field, jumbograms are not supported
+            return Err(BuildError::PacketTooLarge { max: 65535, actual: payload_length });
+        }
+
+        let flow_label = self.flow_label & 0xFFFFF;
+        let first_word = 6 << 28 | (self.traffic_class as u32) << 20 | flow_label;
+
+        let next_header = self.extensions.first().map(|extension| extension.kind()).unwrap_or(IPV6_NEXT_HEADER_TCP);
+
+        let mut bytes = Vec::with_capacity(40 + extensions.len());
+
+        bytes.extend_from_slice(&first_word.to_be_bytes());
+        bytes.extend_from_slice(&(payload_length as u16).to_be_bytes());
+        bytes.extend_from_slice(&[next_header, self.hop_limit]);
+        bytes.extend_from_slice(&self.source_address.octets());
+        bytes.extend_from_slice(&self.destination_address.octets());
+        bytes.extend_from_slice(&extensions);
+
+        Ok(
+            Ipv6Header {
+                bytes,
+                version: 6,
+                traffic_class: self.traffic_class,
+                flow_label,
+                payload_length: payload_length as u16,
+                next_header,
+                hop_limit: self.hop_limit,
+                source_address: self.source_address,
+                destination_address: self.destination_address,
+                extensions: self.extensions.clone(),
+            }
+        )
+
+    }
+
+}
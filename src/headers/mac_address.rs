@@ -0,0 +1,159 @@
+// IEEE 802 MAC-48 address
+// https://standards.ieee.org/products-programs/regauth/
+// https://tools.ietf.org/html/rfc4291#appendix-A
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |      Organisation     |L|G|                                   |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// G (lowest bit of the first byte) marks group addresses, L (the bit above it) locally administered ones
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::AddressParseError;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct MacAddress([u8; 6]);
+
+impl MacAddress {
+
+    pub const BROADCAST: MacAddress = MacAddress([0xFF; 6]);
+    pub const UNSPECIFIED: MacAddress = MacAddress([0; 6]);
+
+    pub const fn new(octets: [u8; 6]) -> MacAddress {
+
+        MacAddress(octets)
+
+    }
+
+    pub fn octets(&self) -> [u8; 6] {
+
+        self.0
+
+    }
+
+    pub fn is_unicast(&self) -> bool {
+
+        self.0[0] & 0x01 == 0
+
+    }
+
+    // Broadcast included
+    pub fn is_multicast(&self) -> bool {
+
+        self.0[0] & 0x01 != 0
+
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+
+        *self == MacAddress::BROADCAST
+
+    }
+
+    pub fn is_locally_administered(&self) -> bool {
+
+        self.0[0] & 0x02 != 0
+
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+
+        *self == MacAddress::UNSPECIFIED
+
+    }
+
+    // Modified EUI-64 for IPv6 addresses: FF:FE in the middle, universal/local bit inverted
+    pub fn get_interface_identifier(&self) -> [u8; 8] {
+
+        let octets = self.0;
+
+        [octets[0] ^ 0x02, octets[1], octets[2], 0xFF, 0xFE, octets[3], octets[4], octets[5]]
+
+    }
+
+}
+
+impl From<[u8; 6]> for MacAddress {
+
+    fn from(octets: [u8; 6]) -> MacAddress {
+
+        MacAddress(octets)
+
+    }
+
+}
+
+impl From<MacAddress> for [u8; 6] {
+
+    fn from(address: MacAddress) -> [u8; 6] {
+
+        address.0
+
+    }
+
+}
+
+impl fmt::Display for MacAddress {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        let octets = self.0;
+
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            octets[0], octets[1], octets[2], octets[3], octets[4], octets[5],
+        )
+
+    }
+
+}
+
+impl fmt::Debug for MacAddress {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        write!(f, "MacAddress({})", self)
+
+    }
+
+}
+
+impl FromStr for MacAddress {
+
+    type Err = AddressParseError;
+
+    // Six colon separated hex bytes, either case
+    fn from_str(s: &str) -> Result<MacAddress, AddressParseError> {
+
+        let error = || AddressParseError { input: s.to_string() };
+
+        let mut octets = [0; 6];
+        let mut parts = s.split(':');
+
+        for octet in octets.iter_mut() {
+
+            let part = parts.next().ok_or_else(error)?;
+
+            if part.len() != 2 || !part.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                return Err(error());
+            }
+
+            *octet = u8::from_str_radix(part, 16).map_err(|_| error())?;
+
+        }
+
+        if parts.next().is_some() {
+            // Cancel if there are more than six bytes
+            return Err(error());
+        }
+
+        Ok(MacAddress(octets))
+
+    }
+
+}
@@ -1,2 +1,18 @@
+pub mod arp_packet;
+pub mod ethernet_header;
 pub mod ip_header;
+pub mod ip_identification;
+pub mod ip_option;
+pub mod ipv6_extension;
+pub mod ipv6_header;
+pub mod mac_address;
+pub mod mpls_label;
+pub mod pppoe_header;
+pub mod pseudo_header;
+pub mod sll_header;
+pub mod tcp_flags;
 pub mod tcp_header;
+pub mod tcp_option;
+pub mod tun_header;
+pub mod virtio_net_header;
+pub mod vlan_tag;
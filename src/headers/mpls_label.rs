@@ -0,0 +1,137 @@
+// MPLS label stack entry
+// https://tools.ietf.org/html/rfc3032
+// https://tools.ietf.org/html/rfc5462
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                Label                  | TC  |S|       TTL     |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// Entries repeat until S (bottom of stack) is set. The payload protocol is not
+// carried, it is guessed from the explicit null labels or the first payload nibble
+
+use crate::error::{Layer, ParseError};
+
+pub const ETHER_TYPE_MPLS_UNICAST: u16 = 0x8847;
+pub const ETHER_TYPE_MPLS_MULTICAST: u16 = 0x8848;
+
+pub const MPLS_LABEL_IPV4_EXPLICIT_NULL: u32 = 0;
+pub const MPLS_LABEL_ROUTER_ALERT: u32 = 1;
+pub const MPLS_LABEL_IPV6_EXPLICIT_NULL: u32 = 2;
+pub const MPLS_LABEL_IMPLICIT_NULL: u32 = 3;      // Signalled only, never on the wire
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MplsLabel {
+
+    pub label: u32,                 // 20 bits
+    pub tc: u8,                     // Traffic class, 3 bits
+    pub bottom_of_stack: bool,
+    pub ttl: u8,
+
+}
+
+impl MplsLabel {
+
+    pub fn new(label: u32) -> MplsLabel {
+
+        MplsLabel {
+            label: label & 0xFFFFF,
+            tc: 0,
+            bottom_of_stack: false,
+            ttl: 64,
+        }
+
+    }
+
+    pub fn is_ether_type(ether_type: u16) -> bool {
+
+        matches!(ether_type, ETHER_TYPE_MPLS_UNICAST | ETHER_TYPE_MPLS_MULTICAST)
+
+    }
+
+    pub fn parse(bytes: [u8; 4]) -> MplsLabel {
+
+        MplsLabel {
+            label: u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 4,
+            tc: (bytes[2] & 0b00001110) >> 1,
+            bottom_of_stack: bytes[2] & 0x01 == 1,
+            ttl: bytes[3],
+        }
+
+    }
+
+    // Entries up to and including the bottom of stack
+    pub fn parse_stack(bytes: &[u8]) -> Result<Vec<MplsLabel>, ParseError> {
+
+        let mut labels = Vec::new();
+        let mut offset = 0;
+
+        loop {
+
+            if bytes.len() < offset + 4 {
+                // Cancel if stack ends before the bottom entry
+                return Err(ParseError::Truncated { layer: Layer::Mpls, offset: 0, expected: offset + 4, actual: bytes.len() });
+            }
+
+            let label = MplsLabel::parse([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            labels.push(label);
+            offset += 4;
+
+            if label.bottom_of_stack {
+                return Ok(labels);
+            }
+
+        }
+
+    }
+
+    // EtherType of what follows the stack, if it can be told
+    pub fn detect_ether_type(labels: &[MplsLabel], payload: &[u8]) -> Option<u16> {
+
+        match labels.last().map(|label| label.label) {
+            Some(MPLS_LABEL_IPV4_EXPLICIT_NULL) => return Some(0x0800),
+            Some(MPLS_LABEL_IPV6_EXPLICIT_NULL) => return Some(0x86DD),
+            _ => (),
+        }
+
+        // Pseudowire control words start with a zero nibble and are left alone
+        match payload.first().map(|byte| byte >> 4) {
+            Some(4) => Some(0x0800),
+            Some(6) => Some(0x86DD),
+            _ => None,
+        }
+
+    }
+
+    pub fn get_bytes(&self) -> [u8; 4] {
+
+        let entry = (self.label & 0xFFFFF) << 12 | ((self.tc as u32) & 0b111) << 9 | (self.bottom_of_stack as u32) << 8 | self.ttl as u32;
+
+        entry.to_be_bytes()
+
+    }
+
+    // Copy with bottom of stack set on the last entry and cleared on all others
+    pub fn terminate_stack(labels: &[MplsLabel]) -> Vec<MplsLabel> {
+
+        labels.iter()
+            .enumerate()
+            .map(|(index, label)| MplsLabel { bottom_of_stack: index == labels.len() - 1, ..*label })
+            .collect()
+
+    }
+
+    pub fn encode_stack(labels: &[MplsLabel]) -> Vec<u8> {
+
+        let mut bytes = Vec::with_capacity(labels.len() * 4);
+
+        for label in MplsLabel::terminate_stack(labels) {
+            bytes.extend_from_slice(&label.get_bytes());
+        }
+
+        bytes
+
+    }
+
+}
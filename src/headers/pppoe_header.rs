@@ -0,0 +1,350 @@
+// PPP over Ethernet, session and discovery stages
+// https://tools.ietf.org/html/rfc2516
+// https://tools.ietf.org/html/rfc1661#section-2
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |  VER  | TYPE  |      CODE     |          SESSION_ID           |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |            LENGTH             |   PPP Protocol or first tag   |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// Discovery tags
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |          TAG_TYPE             |        TAG_LENGTH             |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |          TAG_VALUE ...
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+use crate::error::{Layer, ParseError};
+
+pub const ETHER_TYPE_PPPOE_DISCOVERY: u16 = 0x8863;
+pub const ETHER_TYPE_PPPOE_SESSION: u16 = 0x8864;
+
+pub const PPPOE_CODE_SESSION: u8 = 0x00;
+pub const PPPOE_CODE_PADI: u8 = 0x09;              // Initiation, broadcast by the host
+pub const PPPOE_CODE_PADO: u8 = 0x07;              // Offer from an access concentrator
+pub const PPPOE_CODE_PADR: u8 = 0x19;              // Request to the chosen concentrator
+pub const PPPOE_CODE_PADS: u8 = 0x65;              // Session confirmation, carries the session ID
+pub const PPPOE_CODE_PADT: u8 = 0xA7;              // Termination
+
+pub const PPPOE_TAG_END_OF_LIST: u16 = 0x0000;
+pub const PPPOE_TAG_SERVICE_NAME: u16 = 0x0101;
+pub const PPPOE_TAG_AC_NAME: u16 = 0x0102;
+pub const PPPOE_TAG_HOST_UNIQ: u16 = 0x0103;
+pub const PPPOE_TAG_AC_COOKIE: u16 = 0x0104;
+
+pub const PPP_IPV4: u16 = 0x0021;
+pub const PPP_IPV6: u16 = 0x0057;
+pub const PPP_IPCP: u16 = 0x8021;
+pub const PPP_LCP: u16 = 0xC021;
+
+// Session stage header with the PPP protocol behind it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PppoeHeader {
+
+    pub code: u8,                   // Always 0 in session stage
+    pub session_id: u16,
+    pub length: u16,                // PPP protocol and payload, without Ethernet padding
+    pub protocol: u16,              // PPP protocol
+
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PppoeTag {
+
+    pub tag_type: u16,
+    pub value: Vec<u8>,
+
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PppoeDiscovery {
+
+    pub code: u8,
+    pub session_id: u16,            // 0 until PADS
+    pub tags: Vec<PppoeTag>,
+
+}
+
+// Borrows the discovery packet from the caller's buffer, tags are decoded on access
+#[derive(Debug, Clone, Copy)]
+pub struct PppoeDiscoveryView<'a> {
+
+    bytes: &'a [u8],                // Header and tags, without Ethernet padding
+
+}
+
+// VER and TYPE are both 1, anything else is a different protocol
+fn check_version(bytes: &[u8]) -> Result<(), ParseError> {
+
+    if bytes[0] != 0x11 {
+        return Err(ParseError::UnsupportedVersion { layer: Layer::Pppoe, offset: 0, expected: 0x11, actual: bytes[0] });
+    }
+
+    Ok(())
+
+}
+
+impl PppoeHeader {
+
+    pub fn new(session_id: u16) -> PppoeHeader {
+
+        PppoeHeader {
+            code: PPPOE_CODE_SESSION,
+            session_id,
+            length: 2,
+            protocol: PPP_IPV4,
+        }
+
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<PppoeHeader, ParseError> {
+
+        if bytes.len() < 8 {
+            // Cancel if header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Pppoe, offset: 0, expected: 8, actual: bytes.len() });
+        }
+
+        check_version(bytes)?;
+
+        let length = u16::from_be_bytes([bytes[4], bytes[5]]);
+
+        if length < 2 {
+            // Cancel if there is no room for the PPP protocol
+            return Err(ParseError::InvalidLength { layer: Layer::Pppoe, offset: 4, expected: 2, actual: length as usize });
+        }
+
+        Ok(
+            PppoeHeader {
+                code: bytes[1],
+                session_id: u16::from_be_bytes([bytes[2], bytes[3]]),
+                length,
+                protocol: u16::from_be_bytes([bytes[6], bytes[7]]),
+            }
+        )
+
+    }
+
+    // EtherType matching the PPP protocol, if it has one
+    pub fn get_ether_type(&self) -> Option<u16> {
+
+        match self.protocol {
+            PPP_IPV4 => Some(0x0800),
+            PPP_IPV6 => Some(0x86DD),
+            _ => None,
+        }
+
+    }
+
+    // Unknown EtherTypes leave the protocol unchanged
+    pub fn set_ether_type(&mut self, ether_type: u16) {
+
+        match ether_type {
+            0x0800 => self.protocol = PPP_IPV4,
+            0x86DD => self.protocol = PPP_IPV6,
+            _ => (),
+        }
+
+    }
+
+    // Length of what follows the PPP protocol
+    pub fn get_payload_length(&self) -> usize {
+
+        self.length as usize - 2
+
+    }
+
+    pub fn set_payload_length(&mut self, length: usize) {
+
+        self.length = (length + 2) as u16;
+
+    }
+
+    pub fn get_bytes(&self) -> [u8; 8] {
+
+        let session_id = self.session_id.to_be_bytes();
+        let length = self.length.to_be_bytes();
+        let protocol = self.protocol.to_be_bytes();
+
+        [0x11, self.code, session_id[0], session_id[1], length[0], length[1], protocol[0], protocol[1]]
+
+    }
+
+}
+
+impl PppoeDiscovery {
+
+    pub fn new(code: u8) -> PppoeDiscovery {
+
+        PppoeDiscovery {
+            code,
+            session_id: 0,
+            tags: Vec::new(),
+        }
+
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<PppoeDiscovery, ParseError> {
+
+        Ok(PppoeDiscoveryView::parse(bytes)?.to_owned())
+
+    }
+
+    pub fn get_tag(&self, tag_type: u16) -> Option<&[u8]> {
+
+        self.tags.iter().find(|tag| tag.tag_type == tag_type).map(|tag| &tag.value[..])
+
+    }
+
+    pub fn get_length(&self) -> usize {
+
+        6 + self.tags.iter().map(|tag| 4 + tag.value.len()).sum::<usize>()
+
+    }
+
+    // Tags must fit the 16 bit length field, PacketBuilder::build_pppoe_discovery checks
+    pub fn get_bytes(&self) -> Vec<u8> {
+
+        let mut bytes = Vec::with_capacity(self.get_length());
+
+        bytes.extend_from_slice(&[0x11, self.code]);
+        bytes.extend_from_slice(&self.session_id.to_be_bytes());
+        bytes.extend_from_slice(&((self.get_length() - 6) as u16).to_be_bytes());
+
+        for tag in self.tags.iter() {
+            bytes.extend_from_slice(&tag.tag_type.to_be_bytes());
+            bytes.extend_from_slice(&(tag.value.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(&tag.value);
+        }
+
+        bytes
+
+    }
+
+}
+
+impl<'a> PppoeDiscoveryView<'a> {
+
+    pub fn parse(bytes: &'a [u8]) -> Result<PppoeDiscoveryView<'a>, ParseError> {
+
+        if bytes.len() < 6 {
+            // Cancel if header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Pppoe, offset: 0, expected: 6, actual: bytes.len() });
+        }
+
+        check_version(bytes)?;
+
+        let length = u16::from_be_bytes([bytes[4], bytes[5]]) as usize;
+
+        if bytes.len() < 6 + length {
+            // Cancel if tags are cut short
+            return Err(ParseError::Truncated { layer: Layer::Pppoe, offset: 0, expected: 6 + length, actual: bytes.len() });
+        }
+
+        let view = PppoeDiscoveryView { bytes: &bytes[.. 6 + length] };
+
+        for tag in view.get_tags() {
+            tag?;
+        }
+
+        Ok(view)
+
+    }
+
+    pub fn code(&self) -> u8 {
+
+        self.bytes[1]
+
+    }
+
+    pub fn session_id(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[2], self.bytes[3]])
+
+    }
+
+    pub fn length(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[4], self.bytes[5]])
+
+    }
+
+    pub fn get_tags(&self) -> PppoeTagIter<'a> {
+
+        PppoeTagIter { bytes: self.bytes, offset: 6 }
+
+    }
+
+    pub fn get_length(&self) -> usize {
+
+        self.bytes.len()
+
+    }
+
+    pub fn get_bytes(&self) -> &'a [u8] {
+
+        self.bytes
+
+    }
+
+    pub fn to_owned(self) -> PppoeDiscovery {
+
+        PppoeDiscovery {
+            code: self.code(),
+            session_id: self.session_id(),
+            tags: self.get_tags().filter_map(Result::ok).collect(),
+        }
+
+    }
+
+}
+
+// Walks discovery tags up to End-Of-List or the end of the payload, stops after the first error
+pub struct PppoeTagIter<'a> {
+
+    bytes: &'a [u8],                // Header and tags
+    offset: usize,
+
+}
+
+impl<'a> Iterator for PppoeTagIter<'a> {
+
+    type Item = Result<PppoeTag, ParseError>;
+
+    fn next(&mut self) -> Option<Result<PppoeTag, ParseError>> {
+
+        let end = self.bytes.len();
+        let offset = self.offset;
+
+        if offset >= end {
+            return None;
+        }
+
+        // Nothing follows an error or End-Of-List
+        self.offset = end;
+
+        if offset + 4 > end {
+            return Some(Err(ParseError::InvalidLength { layer: Layer::Pppoe, offset, expected: 4, actual: end - offset }));
+        }
+
+        let tag_type = u16::from_be_bytes([self.bytes[offset], self.bytes[offset + 1]]);
+        let tag_length = u16::from_be_bytes([self.bytes[offset + 2], self.bytes[offset + 3]]) as usize;
+
+        if offset + 4 + tag_length > end {
+            // Cancel if tag overruns the payload
+            return Some(Err(ParseError::InvalidLength { layer: Layer::Pppoe, offset: offset + 2, expected: tag_length, actual: end - offset - 4 }));
+        }
+
+        if tag_type == PPPOE_TAG_END_OF_LIST {
+            return None;
+        }
+
+        self.offset = offset + 4 + tag_length;
+
+        Some(Ok(PppoeTag { tag_type, value: self.bytes[offset + 4 .. offset + 4 + tag_length].to_vec() }))
+
+    }
+
+}
@@ -0,0 +1,126 @@
+// Pseudo headers covered by the TCP and UDP checksums
+// https://tools.ietf.org/html/rfc793#section-3.1
+// https://tools.ietf.org/html/rfc768
+// https://tools.ietf.org/html/rfc8200#section-8.1
+
+// IPv4
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                       Source Address                          |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                    Destination Address                        |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |     Zero      |   Protocol    |     Upper-Layer Length        |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// IPv6
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                  Source Address, 128 bits                     |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |          Final Destination Address, 128 bits                  |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                   Upper-Layer Packet Length                   |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                      zero                     |  Next Header  |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+use std::net::Ipv6Addr;
+
+pub const IP_PROTOCOL_TCP: u8 = 6;
+pub const IP_PROTOCOL_UDP: u8 = 17;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PseudoHeader {
+
+    Ipv4 { source_address: u32, destination_address: u32 },
+    Ipv6 { source_address: Ipv6Addr, destination_address: Ipv6Addr },     // Final destination if a routing header has segments left
+
+}
+
+impl PseudoHeader {
+
+    // Unfolded sum of the pseudo header for an upper layer of length bytes
+    pub fn get_sum(&self, protocol: u8, length: usize) -> u32 {
+
+        let mut sum: u32 = 0;
+
+        match self {
+            PseudoHeader::Ipv4 { source_address, destination_address } => {
+                sum += source_address >> 16;                // Highest 2 bytes of source address
+                sum += source_address & 0xFFFF;             // Lowest 2 bytes of source address
+                sum += destination_address >> 16;           // Highest 2 bytes of destination address
+                sum += destination_address & 0xFFFF;        // Lowest 2 bytes of destination address
+            },
+            PseudoHeader::Ipv6 { source_address, destination_address } => {
+                for segment in source_address.segments().iter().chain(destination_address.segments().iter()) {
+                    sum += *segment as u32;
+                }
+            },
+        }
+
+        // Upper layer length, 32 bits for IPv6, and protocol
+        sum += (length as u32) >> 16;
+        sum += (length as u32) & 0xFFFF;
+        sum += protocol as u32;
+
+        sum
+
+    }
+
+    // Uncomplemented pseudo header sum, left in the checksum field for checksum offload
+    pub fn calculate_partial_checksum(&self, protocol: u8, length: usize) -> u16 {
+
+        fold(self.get_sum(protocol, length))
+
+    }
+
+    // Checksum over the pseudo header and bytes, skipping the checksum field of TCP and UDP.
+    // A computed UDP checksum of zero is sent as all ones
+    pub fn calculate_checksum(&self, protocol: u8, bytes: &[u8]) -> u16 {
+
+        let checksum_offset = match protocol {
+            IP_PROTOCOL_TCP => Some(16),
+            IP_PROTOCOL_UDP => Some(6),
+            _ => None,
+        };
+
+        let data_length = bytes.len();
+        let padding = data_length % 2;
+
+        let mut sum: u32 = 0;
+
+        // Calculate sum of all bytes as 16 bit words
+        for i in (0 .. data_length - padding).step_by(2) {
+            if Some(i) == checksum_offset {
+                // Skip checksum
+                continue
+            }
+            sum += u16::from_be_bytes([bytes[i], bytes[i + 1]]) as u32;
+        }
+
+        if padding == 1 {
+            sum += u16::from_be_bytes([bytes[data_length - 1], 0x0]) as u32;
+        }
+
+        // Add pseudo header sum
+        sum += self.get_sum(protocol, data_length);
+
+        match !fold(sum) {
+            0 if protocol == IP_PROTOCOL_UDP => 0xFFFF,
+            checksum => checksum,
+        }
+
+    }
+
+}
+
+fn fold(mut sum: u32) -> u16 {
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    sum as u16
+
+}
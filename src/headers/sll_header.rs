@@ -0,0 +1,185 @@
+// Linux cooked capture headers, written by libpcap for captures on the "any" device
+// https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL.html
+// https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL2.html
+
+// SLL
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |          Packet Type          |          ARPHRD Type          |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |        Address Length         |            Address            |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                       Address (cont.)                         |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |        Address (cont.)        |      Protocol (EtherType)     |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// SLL2
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |      Protocol (EtherType)     |           Reserved            |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                        Interface Index                        |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |          ARPHRD Type          |  Packet Type  | Address Length|
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                            Address                            |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |                        Address (cont.)                        |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+use crate::error::{Layer, ParseError};
+
+pub const LINUX_SLL_HOST: u8 = 0;                  // Addressed to us
+pub const LINUX_SLL_BROADCAST: u8 = 1;
+pub const LINUX_SLL_MULTICAST: u8 = 2;
+pub const LINUX_SLL_OTHERHOST: u8 = 3;             // Addressed to someone else, seen in promiscuous mode
+pub const LINUX_SLL_OUTGOING: u8 = 4;              // Sent by us
+
+pub const ARPHRD_ETHER: u16 = 1;
+pub const ARPHRD_LOOPBACK: u16 = 772;
+pub const ARPHRD_NONE: u16 = 0xFFFE;               // TUN and other devices without link-layer header
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SllHeader {
+
+    pub packet_type: u8,            // 16 bits on the wire
+    pub arphrd_type: u16,
+    pub address_length: u16,        // May exceed the 8 bytes kept
+    pub address: [u8; 8],
+    pub protocol: u16,
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sll2Header {
+
+    pub protocol: u16,
+    pub interface_index: u32,
+    pub arphrd_type: u16,
+    pub packet_type: u8,
+    pub address_length: u8,         // May exceed the 8 bytes kept
+    pub address: [u8; 8],
+
+}
+
+impl SllHeader {
+
+    pub fn new(protocol: u16) -> SllHeader {
+
+        SllHeader {
+            packet_type: LINUX_SLL_OUTGOING,
+            arphrd_type: ARPHRD_NONE,
+            address_length: 0,
+            address: [0; 8],
+            protocol,
+        }
+
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<SllHeader, ParseError> {
+
+        if bytes.len() < 16 {
+            // Cancel if header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Sll, offset: 0, expected: 16, actual: bytes.len() });
+        }
+
+        let mut address = [0; 8];
+        address.copy_from_slice(&bytes[6 .. 14]);
+
+        Ok(
+            SllHeader {
+                packet_type: bytes[1],
+                arphrd_type: u16::from_be_bytes([bytes[2], bytes[3]]),
+                address_length: u16::from_be_bytes([bytes[4], bytes[5]]),
+                address,
+                protocol: u16::from_be_bytes([bytes[14], bytes[15]]),
+            }
+        )
+
+    }
+
+    pub fn get_address(&self) -> &[u8] {
+
+        &self.address[.. (self.address_length as usize).min(8)]
+
+    }
+
+    pub fn get_bytes(&self) -> [u8; 16] {
+
+        let mut bytes = [0; 16];
+
+        bytes[1] = self.packet_type;
+        bytes[2 .. 4].copy_from_slice(&self.arphrd_type.to_be_bytes());
+        bytes[4 .. 6].copy_from_slice(&self.address_length.to_be_bytes());
+        bytes[6 .. 14].copy_from_slice(&self.address);
+        bytes[14 .. 16].copy_from_slice(&self.protocol.to_be_bytes());
+
+        bytes
+
+    }
+
+}
+
+impl Sll2Header {
+
+    pub fn new(protocol: u16) -> Sll2Header {
+
+        Sll2Header {
+            protocol,
+            interface_index: 0,
+            arphrd_type: ARPHRD_NONE,
+            packet_type: LINUX_SLL_OUTGOING,
+            address_length: 0,
+            address: [0; 8],
+        }
+
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Sll2Header, ParseError> {
+
+        if bytes.len() < 20 {
+            // Cancel if header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Sll, offset: 0, expected: 20, actual: bytes.len() });
+        }
+
+        let mut address = [0; 8];
+        address.copy_from_slice(&bytes[12 .. 20]);
+
+        Ok(
+            Sll2Header {
+                protocol: u16::from_be_bytes([bytes[0], bytes[1]]),
+                interface_index: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+                arphrd_type: u16::from_be_bytes([bytes[8], bytes[9]]),
+                packet_type: bytes[10],
+                address_length: bytes[11],
+                address,
+            }
+        )
+
+    }
+
+    pub fn get_address(&self) -> &[u8] {
+
+        &self.address[.. (self.address_length as usize).min(8)]
+
+    }
+
+    pub fn get_bytes(&self) -> [u8; 20] {
+
+        let mut bytes = [0; 20];
+
+        bytes[0 .. 2].copy_from_slice(&self.protocol.to_be_bytes());
+        bytes[4 .. 8].copy_from_slice(&self.interface_index.to_be_bytes());
+        bytes[8 .. 10].copy_from_slice(&self.arphrd_type.to_be_bytes());
+        bytes[10] = self.packet_type;
+        bytes[11] = self.address_length;
+        bytes[12 .. 20].copy_from_slice(&self.address);
+
+        bytes
+
+    }
+
+}
@@ -0,0 +1,163 @@
+// TCP control bits, including the ECN bits and the reserved bits in front of them
+// https://tools.ietf.org/html/rfc3168#section-6.1
+// https://tools.ietf.org/html/rfc3540
+
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitOrAssign, Not};
+
+// Low 12 bits of header bytes 12 and 13
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TcpFlags(u16);
+
+impl TcpFlags {
+
+    pub const FIN: TcpFlags = TcpFlags(0x001);
+    pub const SYN: TcpFlags = TcpFlags(0x002);
+    pub const RST: TcpFlags = TcpFlags(0x004);
+    pub const PSH: TcpFlags = TcpFlags(0x008);
+    pub const ACK: TcpFlags = TcpFlags(0x010);
+    pub const URG: TcpFlags = TcpFlags(0x020);
+    pub const ECE: TcpFlags = TcpFlags(0x040);
+    pub const CWR: TcpFlags = TcpFlags(0x080);
+    pub const NS: TcpFlags = TcpFlags(0x100);           // AE in AccECN
+    pub const RESERVED: TcpFlags = TcpFlags(0xE00);
+
+    const NAMES: [(TcpFlags, &'static str); 9] = [
+        (TcpFlags::NS, "NS"),
+        (TcpFlags::CWR, "CWR"),
+        (TcpFlags::ECE, "ECE"),
+        (TcpFlags::URG, "URG"),
+        (TcpFlags::ACK, "ACK"),
+        (TcpFlags::PSH, "PSH"),
+        (TcpFlags::RST, "RST"),
+        (TcpFlags::SYN, "SYN"),
+        (TcpFlags::FIN, "FIN"),
+    ];
+
+    pub fn empty() -> TcpFlags {
+
+        TcpFlags(0)
+
+    }
+
+    // Bits above the 12 flag bits are dropped
+    pub fn from_bits(bits: u16) -> TcpFlags {
+
+        TcpFlags(bits & 0xFFF)
+
+    }
+
+    pub fn bits(&self) -> u16 {
+
+        self.0
+
+    }
+
+    pub fn is_empty(&self) -> bool {
+
+        self.0 == 0
+
+    }
+
+    pub fn contains(&self, other: TcpFlags) -> bool {
+
+        self.0 & other.0 == other.0
+
+    }
+
+    pub fn intersects(&self, other: TcpFlags) -> bool {
+
+        self.0 & other.0 != 0
+
+    }
+
+    pub fn insert(&mut self, other: TcpFlags) {
+
+        self.0 |= other.0;
+
+    }
+
+    pub fn remove(&mut self, other: TcpFlags) {
+
+        self.0 &= !other.0;
+
+    }
+
+    pub fn set(&mut self, other: TcpFlags, value: bool) {
+
+        if value {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+
+    }
+
+}
+
+impl BitOr for TcpFlags {
+
+    type Output = TcpFlags;
+
+    fn bitor(self, other: TcpFlags) -> TcpFlags {
+
+        TcpFlags(self.0 | other.0)
+
+    }
+
+}
+
+impl BitOrAssign for TcpFlags {
+
+    fn bitor_assign(&mut self, other: TcpFlags) {
+
+        self.0 |= other.0;
+
+    }
+
+}
+
+impl BitAnd for TcpFlags {
+
+    type Output = TcpFlags;
+
+    fn bitand(self, other: TcpFlags) -> TcpFlags {
+
+        TcpFlags(self.0 & other.0)
+
+    }
+
+}
+
+impl Not for TcpFlags {
+
+    type Output = TcpFlags;
+
+    fn not(self) -> TcpFlags {
+
+        TcpFlags(!self.0 & 0xFFF)
+
+    }
+
+}
+
+impl fmt::Debug for TcpFlags {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        let mut names: Vec<&str> = TcpFlags::NAMES.iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+
+        let reserved = format!("RESERVED({:#x})", (self.0 & TcpFlags::RESERVED.0) >> 9);
+
+        if self.intersects(TcpFlags::RESERVED) {
+            names.insert(0, &reserved);
+        }
+
+        write!(f, "TcpFlags({})", names.join(" | "))
+
+    }
+
+}
@@ -22,11 +22,17 @@
 //  |                             data                              |
 //  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
-use crate::headers::ip_header::{IPHeader, IPHeaderBuilder};
+use crate::error::{BuildError, Layer, ParseError};
+use crate::parse_options::ParseOptions;
+use crate::headers::ip_header::{IPHeader, IPHeaderBuilder, Ipv4HeaderView};
+use crate::headers::ipv6_header::{Ipv6Header, Ipv6HeaderView};
+use crate::headers::pseudo_header::{PseudoHeader, IP_PROTOCOL_TCP};
+use crate::headers::tcp_flags::TcpFlags;
+use crate::headers::tcp_option::{TcpOption, TcpOptionIter};
 
 use std::vec::Vec;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TCPHeader {
 
     pub bytes: Vec<u8>,
@@ -36,6 +42,10 @@ pub struct TCPHeader {
     pub sequence_number: u32,
     pub acknowledgement_number: u32,
     pub data_offset: u8,
+    pub reserved: u8,               // 3 bits between data offset and NS
+    pub ns: bool,
+    pub cwr: bool,
+    pub ece: bool,
     pub urg: bool,
     pub ack: bool,
     pub psh: bool,
@@ -49,12 +59,24 @@ pub struct TCPHeader {
 
 }
 
+// Borrows the header from the caller's buffer and decodes fields on access
+#[derive(Debug, Clone, Copy)]
+pub struct TcpHeaderView<'a> {
+
+    bytes: &'a [u8],                // Header only, options included
+
+}
+
 pub struct TCPHeaderBuilder {
 
     pub source_port: u16,
     pub destination_port: u16,
     pub sequence_number: u32,
     pub acknowledgement_number: u32,
+    pub reserved: u8,               // 3 bits between data offset and NS
+    pub ns: bool,
+    pub cwr: bool,
+    pub ece: bool,
     pub urg: bool,
     pub ack: bool,
     pub psh: bool,
@@ -63,83 +85,298 @@ pub struct TCPHeaderBuilder {
     pub fin: bool,
     pub window: u16,
     pub urgent_ptr: u16,
-    pub options: Vec<u8>,
+    pub options: Vec<TcpOption>,
 
 }
 
 impl TCPHeader {
 
-    pub fn parse(ip_header: &IPHeader, bytes: &[u8]) -> Option<TCPHeader> {
+    pub fn parse(ip_header: &IPHeader, bytes: &[u8]) -> Result<TCPHeader, ParseError> {
 
-        let data_offset = ((bytes[12] & 0xF0) >> 4) * 4;
-        let checksum = u16::from_be_bytes([bytes[16], bytes[17]]);
+        TCPHeader::parse_with(ip_header, bytes, &ParseOptions::default())
 
-        if checksum != TCPHeader::calculate_checksum(ip_header.source_address, ip_header.destination_address, bytes) {
-            // Cancel if invalid checksum
-            return None;
-        }
+    }
 
-        Some(
-            TCPHeader {
-                bytes: bytes[.. data_offset as usize - 1].to_vec(),
-                source_port: u16::from_be_bytes([bytes[0], bytes[1]]),
-                destination_port: u16::from_be_bytes([bytes[2], bytes[3]]),
-                sequence_number: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
-                acknowledgement_number: u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
-                urg: (bytes[13] & 0b00100000) >> 5 == 1,
-                ack: (bytes[13] & 0b00010000) >> 4 == 1,
-                psh: (bytes[13] & 0b00001000) >> 3 == 1,
-                rst: (bytes[13] & 0b00000100) >> 2 == 1,
-                syn: (bytes[13] & 0b00000010) >> 1 == 1,
-                fin: (bytes[13] & 0b00000001) == 1,
-                window: u16::from_be_bytes([bytes[14], bytes[15]]),
-                checksum,
-                urgent_ptr: u16::from_be_bytes([bytes[18], bytes[19]]),
-                options: Vec::new(),
-                data_offset,
-            }
-        )
+    pub fn parse_with(ip_header: &IPHeader, bytes: &[u8], options: &ParseOptions) -> Result<TCPHeader, ParseError> {
+
+        Ok(TcpHeaderView::parse_segment(&ip_header.get_pseudo_header(), bytes, options)?.to_owned())
+
+    }
+
+    pub fn parse_ipv6(ip_header: &Ipv6Header, bytes: &[u8], options: &ParseOptions) -> Result<TCPHeader, ParseError> {
+
+        Ok(TcpHeaderView::parse_segment(&ip_header.get_pseudo_header(), bytes, options)?.to_owned())
 
     }
 
+    // IPv4 only, PseudoHeader covers both versions
     pub fn calculate_checksum(source_address: u32, destination_address: u32, bytes: &[u8]) -> u16 {
 
-        let data_length = bytes.len();
-        let padding = data_length % 2;
+        PseudoHeader::Ipv4 { source_address, destination_address }.calculate_checksum(IP_PROTOCOL_TCP, bytes)
 
-        let mut sum: u32 = 0;
+    }
 
-        // Calculate sum of all TCP bytes as 16 bit words
-        for i in (0 .. data_length - padding).step_by(2) {
-            if i == 16 {
-                // Skip checksum
-                continue
-            }
-            sum += u16::from_be_bytes([bytes[i], bytes[i + 1]]) as u32;
+    // Uncomplemented pseudo header sum, left in the checksum field for NEEDS_CSUM offload
+    pub fn calculate_pseudo_header_sum(source_address: u32, destination_address: u32, length: usize) -> u16 {
+
+        PseudoHeader::Ipv4 { source_address, destination_address }.calculate_partial_checksum(IP_PROTOCOL_TCP, length)
+
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
+
+        &self.bytes[.. self.data_offset as usize]
+
+    }
+
+    pub fn get_options(&self) -> TcpOptionIter<'_> {
+
+        TcpOptionIter::new(self.get_bytes())
+
+    }
+
+    pub fn get_flags(&self) -> TcpFlags {
+
+        let mut flags = TcpFlags::from_bits((self.reserved as u16 & 0b111) << 9);
+        flags.set(TcpFlags::NS, self.ns);
+        flags.set(TcpFlags::CWR, self.cwr);
+        flags.set(TcpFlags::ECE, self.ece);
+        flags.set(TcpFlags::URG, self.urg);
+        flags.set(TcpFlags::ACK, self.ack);
+        flags.set(TcpFlags::PSH, self.psh);
+        flags.set(TcpFlags::RST, self.rst);
+        flags.set(TcpFlags::SYN, self.syn);
+        flags.set(TcpFlags::FIN, self.fin);
+
+        flags
+
+    }
+
+}
+
+impl<'a> TcpHeaderView<'a> {
+
+    pub fn parse(ip_header: &Ipv4HeaderView, bytes: &'a [u8]) -> Result<TcpHeaderView<'a>, ParseError> {
+
+        TcpHeaderView::parse_with(ip_header, bytes, &ParseOptions::default())
+
+    }
+
+    pub fn parse_with(ip_header: &Ipv4HeaderView, bytes: &'a [u8], options: &ParseOptions) -> Result<TcpHeaderView<'a>, ParseError> {
+
+        TcpHeaderView::parse_segment(&ip_header.get_pseudo_header(), bytes, options)
+
+    }
+
+    pub fn parse_ipv6(ip_header: &Ipv6HeaderView, bytes: &'a [u8], options: &ParseOptions) -> Result<TcpHeaderView<'a>, ParseError> {
+
+        TcpHeaderView::parse_segment(&ip_header.get_pseudo_header(), bytes, options)
+
+    }
+
+    // Pseudo header of whichever IP version carries the segment
+    pub(crate) fn parse_segment(pseudo_header: &PseudoHeader, bytes: &'a [u8], options: &ParseOptions) -> Result<TcpHeaderView<'a>, ParseError> {
+
+        if bytes.len() < 20 {
+            // Cancel if fixed part of header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Tcp, offset: 0, expected: 20, actual: bytes.len() });
         }
 
-        if padding == 1 {
-            sum += u16::from_be_bytes([bytes[data_length - 1], 0x0]) as u32;
+        let data_offset = ((bytes[12] & 0xF0) >> 4) as usize * 4;
+
+        if data_offset < 20 {
+            // Invalid header
+            return Err(ParseError::InvalidLength { layer: Layer::Tcp, offset: 12, expected: 20, actual: data_offset });
         }
 
-        // Add pseudo header sum
-        sum += source_address >> 16;                // Highest 2 bytes of source address
-        sum += source_address & 0xFFFF;             // Lowest 2 bytes of source address
-        sum += destination_address >> 16;           // Highest 2 bytes of destination address
-        sum += destination_address & 0xFFFF;        // Lowest 2 bytes of destination address
-        sum += 6 + bytes.len() as u32;              // TCP protocol number + TCP data length
+        if data_offset > bytes.len() {
+            // Cancel if options are cut short
+            return Err(ParseError::Truncated { layer: Layer::Tcp, offset: 0, expected: data_offset, actual: bytes.len() });
+        }
+
+        let reserved = (bytes[12] & 0b00001110) >> 1;
+
+        if options.check_reserved_bits && reserved != 0 {
+            // Reserved bits must be zero
+            return Err(ParseError::ReservedBitsSet { layer: Layer::Tcp, offset: 12, actual: reserved });
+        }
+
+        if options.verify_tcp_checksum {
+
+            let checksum = u16::from_be_bytes([bytes[16], bytes[17]]);
+            let expected_checksum = pseudo_header.calculate_checksum(IP_PROTOCOL_TCP, bytes);
+
+            if checksum != expected_checksum {
+                // Cancel if invalid checksum
+                return Err(ParseError::ChecksumMismatch { layer: Layer::Tcp, offset: 16, expected: expected_checksum, actual: checksum });
+            }
 
-        while sum >> 16 != 0 {
-            sum = (sum & 0xFFFF) + (sum >> 16);
         }
 
-        !sum as u16
+        Ok(TcpHeaderView { bytes: &bytes[.. data_offset] })
 
     }
 
-    pub fn get_bytes(&self) -> &[u8] {
+    pub fn source_port(&self) -> u16 {
 
-        &self.bytes[.. self.data_offset as usize]
+        u16::from_be_bytes([self.bytes[0], self.bytes[1]])
+
+    }
+
+    pub fn destination_port(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[2], self.bytes[3]])
+
+    }
+
+    pub fn sequence_number(&self) -> u32 {
+
+        u32::from_be_bytes([self.bytes[4], self.bytes[5], self.bytes[6], self.bytes[7]])
+
+    }
+
+    pub fn acknowledgement_number(&self) -> u32 {
+
+        u32::from_be_bytes([self.bytes[8], self.bytes[9], self.bytes[10], self.bytes[11]])
+
+    }
+
+    pub fn data_offset(&self) -> u8 {
+
+        ((self.bytes[12] & 0xF0) >> 4) * 4
+
+    }
+
+    pub fn reserved(&self) -> u8 {
+
+        (self.bytes[12] & 0b00001110) >> 1
+
+    }
+
+    pub fn ns(&self) -> bool {
+
+        (self.bytes[12] & 0b00000001) == 1
+
+    }
+
+    pub fn cwr(&self) -> bool {
+
+        (self.bytes[13] & 0b10000000) >> 7 == 1
+
+    }
+
+    pub fn ece(&self) -> bool {
+
+        (self.bytes[13] & 0b01000000) >> 6 == 1
+
+    }
+
+    pub fn urg(&self) -> bool {
+
+        (self.bytes[13] & 0b00100000) >> 5 == 1
+
+    }
+
+    pub fn ack(&self) -> bool {
+
+        (self.bytes[13] & 0b00010000) >> 4 == 1
+
+    }
+
+    pub fn psh(&self) -> bool {
+
+        (self.bytes[13] & 0b00001000) >> 3 == 1
+
+    }
+
+    pub fn rst(&self) -> bool {
+
+        (self.bytes[13] & 0b00000100) >> 2 == 1
+
+    }
+
+    pub fn syn(&self) -> bool {
+
+        (self.bytes[13] & 0b00000010) >> 1 == 1
+
+    }
+
+    pub fn fin(&self) -> bool {
+
+        (self.bytes[13] & 0b00000001) == 1
+
+    }
+
+    pub fn window(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[14], self.bytes[15]])
+
+    }
+
+    pub fn checksum(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[16], self.bytes[17]])
+
+    }
+
+    pub fn urgent_ptr(&self) -> u16 {
+
+        u16::from_be_bytes([self.bytes[18], self.bytes[19]])
+
+    }
+
+    pub fn get_bytes(&self) -> &'a [u8] {
+
+        self.bytes
+
+    }
+
+    pub fn get_options(&self) -> TcpOptionIter<'a> {
+
+        TcpOptionIter::new(self.bytes)
+
+    }
+
+    pub fn get_flags(&self) -> TcpFlags {
+
+        TcpFlags::from_bits(u16::from_be_bytes([self.bytes[12], self.bytes[13]]))
+
+    }
+
+    pub fn to_owned(self) -> TCPHeader {
+
+        TCPHeader {
+            bytes: self.bytes.to_vec(),
+            source_port: self.source_port(),
+            destination_port: self.destination_port(),
+            sequence_number: self.sequence_number(),
+            acknowledgement_number: self.acknowledgement_number(),
+            data_offset: self.data_offset(),
+            reserved: self.reserved(),
+            ns: self.ns(),
+            cwr: self.cwr(),
+            ece: self.ece(),
+            urg: self.urg(),
+            ack: self.ack(),
+            psh: self.psh(),
+            rst: self.rst(),
+            syn: self.syn(),
+            fin: self.fin(),
+            window: self.window(),
+            checksum: self.checksum(),
+            urgent_ptr: self.urgent_ptr(),
+            options: self.bytes[20 ..].to_vec(),
+        }
+
+    }
+
+}
+
+impl Default for TCPHeaderBuilder {
+
+    fn default() -> TCPHeaderBuilder {
+
+        TCPHeaderBuilder::new()
 
     }
 
@@ -154,6 +391,10 @@ impl TCPHeaderBuilder {
             destination_port: 0,
             sequence_number: 0,
             acknowledgement_number: 0,
+            reserved: 0,
+            ns: false,
+            cwr: false,
+            ece: false,
             urg: false,
             ack: false,
             psh: false,
@@ -167,10 +408,39 @@ impl TCPHeaderBuilder {
 
     }
 
-    pub fn build(&self, ip_header_builder: &IPHeaderBuilder, data: &[u8]) -> Option<TCPHeader> {
+    pub fn set_flags(&mut self, flags: TcpFlags) {
 
-        let padding = (4 - self.options.len() % 4) % 4;
-        let data_offset = 20 + self.options.len() + padding;
+        self.reserved = ((flags.bits() & TcpFlags::RESERVED.bits()) >> 9) as u8;
+        self.ns = flags.contains(TcpFlags::NS);
+        self.cwr = flags.contains(TcpFlags::CWR);
+        self.ece = flags.contains(TcpFlags::ECE);
+        self.urg = flags.contains(TcpFlags::URG);
+        self.ack = flags.contains(TcpFlags::ACK);
+        self.psh = flags.contains(TcpFlags::PSH);
+        self.rst = flags.contains(TcpFlags::RST);
+        self.syn = flags.contains(TcpFlags::SYN);
+        self.fin = flags.contains(TcpFlags::FIN);
+
+    }
+
+    pub fn build(&self, ip_header_builder: &IPHeaderBuilder, data: &[u8]) -> Result<TCPHeader, BuildError> {
+
+        self.build_with(&ip_header_builder.get_pseudo_header(), data)
+
+    }
+
+    // Checksum computed against pseudo_header, for IPv6 or any other carrier
+    pub fn build_with(&self, pseudo_header: &PseudoHeader, data: &[u8]) -> Result<TCPHeader, BuildError> {
+
+        let options = TcpOption::encode_all(&self.options);
+
+        if options.len() > 40 {
+            // Cancel if options do not fit the data offset field
+            return Err(BuildError::OptionsTooLong { layer: Layer::Tcp, max: 40, actual: options.len() });
+        }
+
+        let padding = (4 - options.len() % 4) % 4;
+        let data_offset = 20 + options.len() + padding;
 
         let source_port = self.source_port.to_be_bytes();
         let destination_port = self.destination_port.to_be_bytes();
@@ -180,6 +450,8 @@ impl TCPHeaderBuilder {
         let urgent_ptr = self.urgent_ptr.to_be_bytes();
 
         let mut flags: u8 = 0;
+        flags += (self.cwr as u8) << 7;
+        flags += (self.ece as u8) << 6;
         flags += (self.urg as u8) << 5;
         flags += (self.ack as u8) << 4;
         flags += (self.psh as u8) << 3;
@@ -191,37 +463,35 @@ impl TCPHeaderBuilder {
             source_port[0], source_port[1], destination_port[0], destination_port[1],
             sequence_number[0], sequence_number[1], sequence_number[2], sequence_number[3],
             acknowledgement_number[0], acknowledgement_number[1], acknowledgement_number[2], acknowledgement_number[3],
-            ((data_offset / 4) << 4) as u8, flags, window[0], window[1],
+            ((data_offset / 4) << 4) as u8 | (self.reserved & 0b111) << 1 | self.ns as u8, flags, window[0], window[1],
             0, 0, urgent_ptr[0], urgent_ptr[1],
         );
 
-        for &option in self.options.iter() {
-            bytes.push(option);
-        }
-
-        if padding > 0 {
-            for _ in 0 .. padding {
-                bytes.push(0);
-            }
-        }
+        bytes.extend_from_slice(&options);
+        bytes.resize(data_offset, 0);
 
         let mut tcp_section = bytes.clone();
         tcp_section.extend_from_slice(data);
 
-        let checksum = TCPHeader::calculate_checksum(ip_header_builder.source_address, ip_header_builder.destination_address, &tcp_section[..]);
+        let checksum = pseudo_header.calculate_checksum(IP_PROTOCOL_TCP, &tcp_section[..]);
         let checksum_bytes = checksum.to_be_bytes();
 
         bytes[16] = checksum_bytes[0];
         bytes[17] = checksum_bytes[1];
 
-        Some (
+        Ok(
             TCPHeader {
+                options: bytes[20 ..].to_vec(),
                 bytes,
                 source_port: self.source_port,
                 destination_port: self.destination_port,
                 sequence_number: self.sequence_number,
                 acknowledgement_number: self.acknowledgement_number,
                 data_offset: data_offset as u8,
+                reserved: self.reserved & 0b111,
+                ns: self.ns,
+                cwr: self.cwr,
+                ece: self.ece,
                 urg: self.urg,
                 ack: self.ack,
                 psh: self.psh,
@@ -231,7 +501,6 @@ impl TCPHeaderBuilder {
                 window: self.window,
                 checksum,
                 urgent_ptr: self.urgent_ptr,
-                options: self.options.clone(),
             }
         )
 
@@ -0,0 +1,219 @@
+// TCP options
+// https://tools.ietf.org/html/rfc793#section-3.1
+// https://tools.ietf.org/html/rfc2018
+// https://tools.ietf.org/html/rfc7323
+// https://www.iana.org/assignments/tcp-parameters/tcp-parameters.xhtml
+
+use crate::error::{Layer, ParseError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpOption {
+
+    EndOfList,                                          // Kind 0
+    Nop,                                                // Kind 1
+    Mss(u16),                                           // Kind 2
+    WindowScale(u8),                                    // Kind 3
+    SackPermitted,                                      // Kind 4
+    Sack(Vec<(u32, u32)>),                              // Kind 5, left and right edge of each block
+    Timestamps { value: u32, echo_reply: u32 },         // Kind 8
+    Unknown { kind: u8, data: Vec<u8> },
+
+}
+
+// Decodes options one by one, stops after End of Option List or the first error
+pub struct TcpOptionIter<'a> {
+
+    bytes: &'a [u8],                // Whole TCP header
+    offset: usize,                  // Next option, counted from header start
+    done: bool,
+
+}
+
+impl TcpOption {
+
+    pub fn kind(&self) -> u8 {
+
+        match self {
+            TcpOption::EndOfList => 0,
+            TcpOption::Nop => 1,
+            TcpOption::Mss(_) => 2,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 4,
+            TcpOption::Sack(_) => 5,
+            TcpOption::Timestamps { .. } => 8,
+            TcpOption::Unknown { kind, .. } => *kind,
+        }
+
+    }
+
+    pub fn get_length(&self) -> usize {
+
+        match self {
+            TcpOption::EndOfList | TcpOption::Nop => 1,
+            TcpOption::Mss(_) => 4,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 2,
+            TcpOption::Sack(blocks) => 2 + blocks.len() * 8,
+            TcpOption::Timestamps { .. } => 10,
+            TcpOption::Unknown { data, .. } => 2 + data.len(),
+        }
+
+    }
+
+    // Offset modulo 4 the option should start at so its fields are word aligned
+    fn get_alignment(&self) -> Option<usize> {
+
+        match self {
+            TcpOption::Mss(_) => Some(0),
+            TcpOption::WindowScale(_) => Some(1),
+            TcpOption::Sack(_) | TcpOption::Timestamps { .. } => Some(2),
+            _ => None,
+        }
+
+    }
+
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+
+        let kind = self.kind();
+
+        match self {
+            TcpOption::EndOfList | TcpOption::Nop => bytes.push(kind),
+            TcpOption::Mss(mss) => {
+                bytes.extend_from_slice(&[kind, 4]);
+                bytes.extend_from_slice(&mss.to_be_bytes());
+            },
+            TcpOption::WindowScale(shift) => bytes.extend_from_slice(&[kind, 3, *shift]),
+            TcpOption::SackPermitted => bytes.extend_from_slice(&[kind, 2]),
+            TcpOption::Sack(blocks) => {
+                bytes.extend_from_slice(&[kind, self.get_length() as u8]);
+                for (left_edge, right_edge) in blocks.iter() {
+                    bytes.extend_from_slice(&left_edge.to_be_bytes());
+                    bytes.extend_from_slice(&right_edge.to_be_bytes());
+                }
+            },
+            TcpOption::Timestamps { value, echo_reply } => {
+                bytes.extend_from_slice(&[kind, 10]);
+                bytes.extend_from_slice(&value.to_be_bytes());
+                bytes.extend_from_slice(&echo_reply.to_be_bytes());
+            },
+            TcpOption::Unknown { data, .. } => {
+                bytes.extend_from_slice(&[kind, self.get_length() as u8]);
+                bytes.extend_from_slice(data);
+            },
+        }
+
+    }
+
+    // Serializes options in order, inserting NOPs in front of options that want alignment
+    pub fn encode_all(options: &[TcpOption]) -> Vec<u8> {
+
+        let mut bytes = Vec::new();
+
+        for option in options.iter() {
+
+            if let Some(alignment) = option.get_alignment() {
+                while bytes.len() % 4 != alignment {
+                    TcpOption::Nop.write(&mut bytes);
+                }
+            }
+
+            option.write(&mut bytes);
+
+        }
+
+        bytes
+
+    }
+
+}
+
+impl<'a> TcpOptionIter<'a> {
+
+    pub(crate) fn new(header: &'a [u8]) -> TcpOptionIter<'a> {
+
+        TcpOptionIter {
+            bytes: header,
+            offset: 20,
+            done: false,
+        }
+
+    }
+
+    fn decode(&self) -> Result<(TcpOption, usize), ParseError> {
+
+        let bytes = &self.bytes[self.offset ..];
+        let kind = bytes[0];
+
+        match kind {
+            0 => return Ok((TcpOption::EndOfList, 1)),
+            1 => return Ok((TcpOption::Nop, 1)),
+            _ => (),
+        }
+
+        let invalid = |length: usize| ParseError::InvalidOption { layer: Layer::Tcp, offset: self.offset, kind, length };
+
+        if bytes.len() < 2 {
+            // Length byte missing
+            return Err(invalid(bytes.len()));
+        }
+
+        let length = bytes[1] as usize;
+
+        if length < 2 || length > bytes.len() {
+            // Length does not cover kind and length bytes or runs past the header
+            return Err(invalid(length));
+        }
+
+        let data = &bytes[2 .. length];
+
+        let option = match kind {
+            2 if length == 4 => TcpOption::Mss(u16::from_be_bytes([data[0], data[1]])),
+            3 if length == 3 => TcpOption::WindowScale(data[0]),
+            4 if length == 2 => TcpOption::SackPermitted,
+            5 if length > 2 && data.len() % 8 == 0 => TcpOption::Sack(
+                data.chunks(8)
+                    .map(|block| (
+                        u32::from_be_bytes([block[0], block[1], block[2], block[3]]),
+                        u32::from_be_bytes([block[4], block[5], block[6], block[7]]),
+                    ))
+                    .collect()
+            ),
+            8 if length == 10 => TcpOption::Timestamps {
+                value: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                echo_reply: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            },
+            2 | 3 | 4 | 5 | 8 => return Err(invalid(length)),
+            _ => TcpOption::Unknown { kind, data: data.to_vec() },
+        };
+
+        Ok((option, length))
+
+    }
+
+}
+
+impl<'a> Iterator for TcpOptionIter<'a> {
+
+    type Item = Result<TcpOption, ParseError>;
+
+    fn next(&mut self) -> Option<Result<TcpOption, ParseError>> {
+
+        if self.done || self.offset >= self.bytes.len() {
+            return None;
+        }
+
+        match self.decode() {
+            Ok((option, length)) => {
+                self.offset += length;
+                self.done = option == TcpOption::EndOfList;
+                Some(Ok(option))
+            },
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            },
+        }
+
+    }
+
+}
@@ -0,0 +1,65 @@
+// TUN/TAP packet information prefix
+// https://www.kernel.org/doc/Documentation/networking/tuntap.txt
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/if_tun.h
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |             Flags             |     Protocol (EtherType)      |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+use crate::error::{Layer, ParseError};
+
+pub const TUN_PKT_STRIP: u16 = 0x0001;     // Packet did not fit the read buffer and was cut
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TunHeader {
+
+    pub flags: u16,
+    pub proto: u16,
+
+}
+
+impl TunHeader {
+
+    pub fn new(proto: u16) -> TunHeader {
+
+        TunHeader {
+            flags: 0,
+            proto,
+        }
+
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<TunHeader, ParseError> {
+
+        if bytes.len() < 4 {
+            // Cancel if TUN/TAP header is cut short
+            return Err(ParseError::Truncated { layer: Layer::Tun, offset: 0, expected: 4, actual: bytes.len() });
+        }
+
+        Ok(
+            TunHeader {
+                flags: u16::from_be_bytes([bytes[0], bytes[1]]),
+                proto: u16::from_be_bytes([bytes[2], bytes[3]]),
+            }
+        )
+
+    }
+
+    pub fn is_stripped(&self) -> bool {
+
+        self.flags & TUN_PKT_STRIP != 0
+
+    }
+
+    pub fn get_bytes(&self) -> [u8; 4] {
+
+        let flags = self.flags.to_be_bytes();
+        let proto = self.proto.to_be_bytes();
+
+        [flags[0], flags[1], proto[0], proto[1]]
+
+    }
+
+}
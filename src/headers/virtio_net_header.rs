@@ -0,0 +1,113 @@
+// virtio-net header, prepended by TUN/TAP devices opened with IFF_VNET_HDR
+// https://docs.oasis-open.org/virtio/virtio/v1.1/csprd01/virtio-v1.1-csprd01.html#x1-2050006
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/virtio_net.h
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |     Flags     |   GSO Type    |         Header Length         |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |           GSO Size            |        Checksum Start         |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |        Checksum Offset        |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// 16 bit fields are little endian, as TUN uses them on x86 and ARM hosts
+
+use crate::error::{Layer, ParseError};
+
+pub const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 0x01;  // Checksum from start + offset still has to be finished
+pub const VIRTIO_NET_HDR_F_DATA_VALID: u8 = 0x02;  // Checksum already verified
+
+pub const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+pub const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+pub const VIRTIO_NET_HDR_GSO_UDP: u8 = 3;
+pub const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+pub const VIRTIO_NET_HDR_GSO_ECN: u8 = 0x80;      // Combined with a GSO type, segments carry ECN
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtioNetHeader {
+
+    pub flags: u8,
+    pub gso_type: u8,
+    pub header_length: u16,         // IP and TCP headers in front of the data
+    pub gso_size: u16,              // Data bytes per segment
+    pub checksum_start: u16,        // From the IP header
+    pub checksum_offset: u16,       // From checksum_start
+
+}
+
+impl Default for VirtioNetHeader {
+
+    fn default() -> VirtioNetHeader {
+
+        VirtioNetHeader::new()
+
+    }
+
+}
+
+impl VirtioNetHeader {
+
+    pub fn new() -> VirtioNetHeader {
+
+        VirtioNetHeader {
+            flags: 0,
+            gso_type: VIRTIO_NET_HDR_GSO_NONE,
+            header_length: 0,
+            gso_size: 0,
+            checksum_start: 0,
+            checksum_offset: 0,
+        }
+
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<VirtioNetHeader, ParseError> {
+
+        if bytes.len() < 10 {
+            // Cancel if virtio-net header is cut short
+            return Err(ParseError::Truncated { layer: Layer::VirtioNet, offset: 0, expected: 10, actual: bytes.len() });
+        }
+
+        Ok(
+            VirtioNetHeader {
+                flags: bytes[0],
+                gso_type: bytes[1],
+                header_length: u16::from_le_bytes([bytes[2], bytes[3]]),
+                gso_size: u16::from_le_bytes([bytes[4], bytes[5]]),
+                checksum_start: u16::from_le_bytes([bytes[6], bytes[7]]),
+                checksum_offset: u16::from_le_bytes([bytes[8], bytes[9]]),
+            }
+        )
+
+    }
+
+    pub fn needs_checksum(&self) -> bool {
+
+        self.flags & VIRTIO_NET_HDR_F_NEEDS_CSUM != 0
+
+    }
+
+    // Whether this is a TCP super-packet to be cut into gso_size segments
+    pub fn is_tcp_gso(&self) -> bool {
+
+        matches!(self.gso_type & !VIRTIO_NET_HDR_GSO_ECN, VIRTIO_NET_HDR_GSO_TCPV4 | VIRTIO_NET_HDR_GSO_TCPV6) && self.gso_size > 0
+
+    }
+
+    pub fn get_bytes(&self) -> [u8; 10] {
+
+        let mut bytes = [0; 10];
+
+        bytes[0] = self.flags;
+        bytes[1] = self.gso_type;
+        bytes[2 .. 4].copy_from_slice(&self.header_length.to_le_bytes());
+        bytes[4 .. 6].copy_from_slice(&self.gso_size.to_le_bytes());
+        bytes[6 .. 8].copy_from_slice(&self.checksum_start.to_le_bytes());
+        bytes[8 .. 10].copy_from_slice(&self.checksum_offset.to_le_bytes());
+
+        bytes
+
+    }
+
+}
@@ -0,0 +1,73 @@
+// IEEE 802.1Q VLAN tag, stacked behind an 802.1ad service tag for QinQ
+// https://standards.ieee.org/standard/802_1Q-2018.html
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//  |     Tag Protocol Identifier   | PCP |D|         VLAN ID       |
+//  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+pub const TPID_8021Q: u16 = 0x8100;            // Customer tag
+pub const TPID_8021AD: u16 = 0x88A8;           // Service tag
+pub const TPID_QINQ: u16 = 0x9100;             // Pre-standard service tag
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VlanTag {
+
+    pub tpid: u16,
+    pub pcp: u8,                    // Priority code point, 3 bits
+    pub dei: bool,                  // Drop eligible indicator
+    pub vid: u16,                   // 12 bits
+
+}
+
+impl VlanTag {
+
+    pub fn new(vid: u16) -> VlanTag {
+
+        VlanTag {
+            tpid: TPID_8021Q,
+            pcp: 0,
+            dei: false,
+            vid: vid & 0xFFF,
+        }
+
+    }
+
+    pub fn service(vid: u16) -> VlanTag {
+
+        VlanTag {
+            tpid: TPID_8021AD,
+            ..VlanTag::new(vid)
+        }
+
+    }
+
+    pub fn is_tpid(ether_type: u16) -> bool {
+
+        matches!(ether_type, TPID_8021Q | TPID_8021AD | TPID_QINQ)
+
+    }
+
+    // TPID and the two TCI bytes following it
+    pub fn parse(tpid: u16, tci: [u8; 2]) -> VlanTag {
+
+        VlanTag {
+            tpid,
+            pcp: tci[0] >> 5,
+            dei: (tci[0] & 0b00010000) >> 4 == 1,
+            vid: u16::from_be_bytes([tci[0] & 0xF, tci[1]]),
+        }
+
+    }
+
+    pub fn get_bytes(&self) -> [u8; 4] {
+
+        let tpid = self.tpid.to_be_bytes();
+        let tci = (((self.pcp as u16) & 0b111) << 13 | (self.dei as u16) << 12 | (self.vid & 0xFFF)).to_be_bytes();
+
+        [tpid[0], tpid[1], tci[0], tci[1]]
+
+    }
+
+}
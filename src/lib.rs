@@ -1,74 +1,106 @@
+pub mod error;
 pub mod packet;
 pub mod headers;
+pub mod link_layer;
+pub mod parse_options;
+pub mod reassembly;
 //pub mod packet_builder;
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
 
-    use crate::packet::{Packet, PacketBuilder};
+    use std::net::Ipv6Addr;
+    use std::time::{Duration, Instant};
 
-    #[test]
-    fn test_request_parsing() {
-
-        const bytes_in: usize = 65;
-
-        // These would be set by TUN/TAP in actual program
-        let mut buffer: [u8; 1504] = [0; 1504];
-        let bytes_read = bytes_in;
+    use crate::error::{AddressParseError, BuildError, Layer, ParseError, ReassemblyError};
+    use crate::headers::arp_packet::{ArpPacket, ARP_REPLY, ARP_REQUEST};
+    use crate::headers::ethernet_header::{EthernetHeader, EthernetHeaderBuilder};
+    use crate::headers::ip_header::IPHeader;
+    use crate::headers::ip_identification::{Identification, PER_DESTINATION_LIMIT};
+    use crate::headers::ip_option::Ipv4Option;
+    use crate::headers::ipv6_extension::{Ipv6Extension, IPV6_NEXT_HEADER_ESP, IPV6_NEXT_HEADER_ROUTING};
+    use crate::headers::ipv6_header::{Ipv6Header, Ipv6HeaderBuilder};
+    use crate::headers::mac_address::MacAddress;
+    use crate::headers::mpls_label::{MplsLabel, MPLS_LABEL_IPV4_EXPLICIT_NULL, MPLS_LABEL_IPV6_EXPLICIT_NULL};
+    use crate::headers::pppoe_header::{PppoeDiscovery, PppoeHeader, PppoeTag, ETHER_TYPE_PPPOE_DISCOVERY, ETHER_TYPE_PPPOE_SESSION, PPPOE_CODE_PADI, PPPOE_TAG_AC_COOKIE, PPPOE_TAG_AC_NAME, PPPOE_TAG_HOST_UNIQ, PPPOE_TAG_SERVICE_NAME, PPP_LCP};
+    use crate::headers::pseudo_header::{PseudoHeader, IP_PROTOCOL_TCP, IP_PROTOCOL_UDP};
+    use crate::headers::sll_header::{Sll2Header, SllHeader, ARPHRD_ETHER, LINUX_SLL_HOST, LINUX_SLL_OUTGOING};
+    use crate::headers::tcp_flags::TcpFlags;
+    use crate::headers::tcp_header::{TCPHeader, TCPHeaderBuilder};
+    use crate::headers::tcp_option::TcpOption;
+    use crate::headers::tun_header::{TunHeader, TUN_PKT_STRIP};
+    use crate::headers::virtio_net_header::{VirtioNetHeader, VIRTIO_NET_HDR_F_NEEDS_CSUM, VIRTIO_NET_HDR_GSO_NONE, VIRTIO_NET_HDR_GSO_TCPV4, VIRTIO_NET_HDR_GSO_TCPV6};
+    use crate::headers::vlan_tag::{VlanTag, TPID_8021AD, TPID_8021Q};
+    use crate::link_layer::{Framing, LinkLayer};
+    use crate::packet::{NetworkHeader, Packet, PacketBuilder, PacketView};
+    use crate::parse_options::{ParseOptions, ValidationPolicy};
+    use crate::reassembly::{OverlapPolicy, Reassembler};
 
-        // Fake TUN/TAP request
-        let request: [u8; bytes_in] = [
+    // Fake TUN/TAP request
+    const REQUEST: [u8; 65] = [
 
-            0x00, 0x00, 0x08, 0x00,                                     // TUN/TAP https://www.kernel.org/doc/Documentation/networking/tuntap.txt
+        0x00, 0x00, 0x08, 0x00,                                     // TUN/TAP https://www.kernel.org/doc/Documentation/networking/tuntap.txt
 
-            0x45, 0x00, 0x00, 0x3D, 0xE1, 0x28, 0x40, 0x00,             // Internet header
-            0x40, 0x06, 0xD8, 0x0D, 0xC0, 0xA8, 0x00, 0x32,
-            0xC0, 0xA8, 0x00, 0x02,
+        0x45, 0x00, 0x00, 0x3D, 0xE1, 0x28, 0x40, 0x00,             // Internet header
+        0x40, 0x06, 0xD8, 0x0D, 0xC0, 0xA8, 0x00, 0x32,
+        0xC0, 0xA8, 0x00, 0x02,
 
-            0xB3, 0xDE, 0x01, 0xBB, 0x99, 0xAF, 0x23, 0x0B,             // TCP header
-            0x00, 0x00, 0x00, 0x00, 0xA0, 0x02, 0xFA, 0xF0,
-            0xCC, 0xC8, 0x00, 0x00, 0x02, 0x04, 0x05, 0xB4,
-            0x04, 0x02, 0x08, 0x0A, 0xC0, 0x1B, 0x8C, 0x50,
-            0x00, 0x00, 0x00, 0x00, 0x01, 0x03, 0x03, 0x07,
+        0xB3, 0xDE, 0x01, 0xBB, 0x99, 0xAF, 0x23, 0x0B,             // TCP header
+        0x00, 0x00, 0x00, 0x00, 0xA0, 0x02, 0xFA, 0xF0,
+        0xCC, 0xC8, 0x00, 0x00, 0x02, 0x04, 0x05, 0xB4,
+        0x04, 0x02, 0x08, 0x0A, 0xC0, 0x1B, 0x8C, 0x50,
+        0x00, 0x00, 0x00, 0x00, 0x01, 0x03, 0x03, 0x07,
 
-            0x40,                                                       // Data
+        0x40,                                                       // Data
 
-        ];
+    ];
 
-        for (index, byte) in request.iter().enumerate() {
-            buffer[index] = *byte;
-        }
+    #[test]
+    fn test_request_parsing() {
 
-        let request_packet = Packet::parse(buffer, bytes_in);
+        let request_packet = Packet::parse(&REQUEST);
 
-        assert_eq!(request_packet.is_some(), true);
+        assert_eq!(request_packet.is_ok(), true);
         let request_packet = request_packet.unwrap();
+        let ip_header = request_packet.get_ip_header().unwrap();
+        let tcp_header = request_packet.tcp_header.as_ref().unwrap();
+
+        assert_eq!(ip_header.version, 4);
+        assert_eq!(ip_header.total_length, 61);
+        assert_eq!(ip_header.header_length, 20);
+        assert_eq!(ip_header.get_data_length(), 41);
+        assert_eq!(ip_header.dscp, 0);
+        assert_eq!(ip_header.ecn, 0);
+        assert_eq!(ip_header.identification, 0xE128);
+        assert_eq!(ip_header.dont_fragment, true);
+        assert_eq!(ip_header.more_fragments, false);
+        assert_eq!(ip_header.fragment_offset, 0);
+        assert_eq!(ip_header.ttl, 64);
+        assert_eq!(ip_header.protocol, 6);
+        assert_eq!(ip_header.source_address, 0xC0A80032);
+        assert_eq!(ip_header.get_source_address_str(), "192.168.0.50");
+        assert_eq!(ip_header.destination_address, 0xC0A80002);
+        assert_eq!(ip_header.get_destination_address_str(), "192.168.0.2");
 
-        assert_eq!(request_packet.ip_header.version, 4);
-        assert_eq!(request_packet.ip_header.total_length, 61);
-        assert_eq!(request_packet.ip_header.header_length, 20);
-        assert_eq!(request_packet.ip_header.get_data_length(), 41);
-        assert_eq!(request_packet.ip_header.ttl, 64);
-        assert_eq!(request_packet.ip_header.protocol, 6);
-        assert_eq!(request_packet.ip_header.source_address, 0xC0A80032);
-        assert_eq!(request_packet.ip_header.get_source_address_str(), "192.168.0.50");
-        assert_eq!(request_packet.ip_header.destination_address, 0xC0A80002);
-        assert_eq!(request_packet.ip_header.get_destination_address_str(), "192.168.0.2");
-
-        assert_eq!(request_packet.tcp_header.source_port, 46046);
-        assert_eq!(request_packet.tcp_header.destination_port, 443);
-        assert_eq!(request_packet.tcp_header.sequence_number, 2578391819);
-        assert_eq!(request_packet.tcp_header.acknowledgement_number, 0);
-        assert_eq!(request_packet.tcp_header.data_offset, 40);
-        assert_eq!(request_packet.tcp_header.urg, false);
-        assert_eq!(request_packet.tcp_header.ack, false);
-        assert_eq!(request_packet.tcp_header.psh, false);
-        assert_eq!(request_packet.tcp_header.rst, false);
-        assert_eq!(request_packet.tcp_header.syn, true);
-        assert_eq!(request_packet.tcp_header.fin, false);
-        assert_eq!(request_packet.tcp_header.window, 64240);
-        assert_eq!(request_packet.tcp_header.checksum, 0xCCC8);
-        assert_eq!(request_packet.tcp_header.urgent_ptr, 0);
+        assert_eq!(tcp_header.source_port, 46046);
+        assert_eq!(tcp_header.destination_port, 443);
+        assert_eq!(tcp_header.sequence_number, 2578391819);
+        assert_eq!(tcp_header.acknowledgement_number, 0);
+        assert_eq!(tcp_header.data_offset, 40);
+        assert_eq!(tcp_header.reserved, 0);
+        assert_eq!(tcp_header.ns, false);
+        assert_eq!(tcp_header.cwr, false);
+        assert_eq!(tcp_header.ece, false);
+        assert_eq!(tcp_header.urg, false);
+        assert_eq!(tcp_header.ack, false);
+        assert_eq!(tcp_header.psh, false);
+        assert_eq!(tcp_header.rst, false);
+        assert_eq!(tcp_header.syn, true);
+        assert_eq!(tcp_header.fin, false);
+        assert_eq!(tcp_header.window, 64240);
+        assert_eq!(tcp_header.checksum, 0xCCC8);
+        assert_eq!(tcp_header.urgent_ptr, 0);
 
         assert_eq!(request_packet.get_tcp_data(), &[0x40]);
 
@@ -95,13 +127,13 @@ mod tests {
         packet_builder.tcp_header_builder.fin = false;
         packet_builder.tcp_header_builder.window = 64240;
         packet_builder.tcp_header_builder.urgent_ptr = 50;
-        packet_builder.tcp_header_builder.options.push(128);
+        packet_builder.tcp_header_builder.options.push(TcpOption::Unknown { kind: 128, data: Vec::new() });
 
         packet_builder.bytes.push(64);
 
         let packet = packet_builder.build();
 
-        assert_eq!(packet.is_some(), true);
+        assert_eq!(packet.is_ok(), true);
         let packet = packet.unwrap();
 
         let bytes: [u8; 49] = [
@@ -114,7 +146,7 @@ mod tests {
 
             0xB3, 0xDE, 0x01, 0xBB, 0x99, 0xAF, 0x23, 0x0B,             // TCP header
             0x00, 0x00, 0x00, 0x32, 0x60, 0x2A, 0xFA, 0xF0,
-            0xF0, 0x86, 0x00, 0x32, 0x80, 0x00, 0x00, 0x00,
+            0xF0, 0x84, 0x00, 0x32, 0x80, 0x02, 0x00, 0x00,
 
             0x40,                                                       // Data
 
@@ -124,4 +156,1942 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_parse_errors() {
+
+        // LLDP TUN proto
+        let mut request = REQUEST;
+        request[2] = 0x88;
+        request[3] = 0xCC;
+
+        assert_eq!(
+            Packet::parse(&request).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Tun, offset: 2, expected: 0x0800, actual: 0x88CC }),
+        );
+
+        // UDP protocol byte
+        let mut request = REQUEST;
+        request[13] = 17;
+
+        assert_eq!(
+            Packet::parse(&request).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Ipv4, offset: 13, expected: 6, actual: 17 }),
+        );
+
+        // IHL of 4 words
+        let mut request = REQUEST;
+        request[4] = 0x44;
+
+        assert_eq!(
+            Packet::parse(&request).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Ipv4, offset: 4, expected: 20, actual: 16 }),
+        );
+
+        // Last data byte missing
+        assert_eq!(
+            Packet::parse(&REQUEST[.. REQUEST.len() - 1]).err(),
+            Some(ParseError::Truncated { layer: Layer::Ipv4, offset: 4, expected: 61, actual: 60 }),
+        );
+
+        // Data byte changed
+        let mut request = REQUEST;
+        request[64] = 0x41;
+
+        let error = Packet::parse(&request).err().unwrap();
+
+        assert_eq!(error, ParseError::ChecksumMismatch { layer: Layer::Tcp, offset: 40, expected: 0xCBC8, actual: 0xCCC8 });
+        assert_eq!(error.layer(), Layer::Tcp);
+        assert_eq!(error.offset(), 40);
+
+    }
+
+    #[test]
+    fn test_build_errors() {
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.options = vec![Ipv4Option::Nop; 41];
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::OptionsTooLong { layer: Layer::Ipv4, max: 40, actual: 41 }));
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.tcp_header_builder.options = vec![TcpOption::Nop; 41];
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::OptionsTooLong { layer: Layer::Tcp, max: 40, actual: 41 }));
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.bytes.extend_from_slice(&[0; 65496]);
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::PacketTooLarge { max: 65535, actual: 65536 }));
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.max_length = 9000;
+        packet_builder.bytes.extend_from_slice(&[0; 8961]);
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::PacketTooLarge { max: 9000, actual: 9001 }));
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.mtu = Some(1500);
+        packet_builder.bytes.extend_from_slice(&[0; 1461]);
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::ExceedsMtu { mtu: 1500, actual: 1501 }));
+
+    }
+
+    #[test]
+    fn test_truncated_packets() {
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.options.push(Ipv4Option::Nop);
+        packet_builder.tcp_header_builder.options = vec![TcpOption::Nop; 5];
+        packet_builder.bytes.extend_from_slice(b"data");
+
+        let built = packet_builder.build().unwrap();
+
+        for request in [&REQUEST[..], built.get_bytes()].iter() {
+
+            for length in 0 .. request.len() {
+                assert!(Packet::parse(&request[.. length]).is_err(), "prefix of {} bytes parsed", length);
+            }
+
+            assert!(Packet::parse(request).is_ok());
+
+            let ip_bytes = &request[4 ..];
+            let ip_header = IPHeader::parse(ip_bytes).unwrap();
+
+            for length in 0 .. ip_header.header_length as usize {
+                assert!(IPHeader::parse(&ip_bytes[.. length]).is_err(), "IP header prefix of {} bytes parsed", length);
+            }
+
+            let tcp_bytes = &ip_bytes[ip_header.header_length as usize ..];
+            let tcp_header = TCPHeader::parse(&ip_header, tcp_bytes).unwrap();
+
+            for length in 0 .. tcp_header.data_offset as usize {
+                assert!(TCPHeader::parse(&ip_header, &tcp_bytes[.. length]).is_err(), "TCP header prefix of {} bytes parsed", length);
+            }
+
+        }
+
+    }
+
+    #[test]
+    fn test_hostile_packets() {
+
+        // IHL of 15 words in a 61 byte datagram without options
+        let mut request = REQUEST;
+        request[4] = 0x4F;
+
+        assert_eq!(
+            IPHeader::parse(&request[4 .. 40]).err(),
+            Some(ParseError::Truncated { layer: Layer::Ipv4, offset: 0, expected: 60, actual: 36 }),
+        );
+
+        // Total length smaller than the IP header
+        let mut request = REQUEST;
+        request[7] = 0x10;
+
+        assert_eq!(
+            Packet::parse(&request).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Ipv4, offset: 6, expected: 20, actual: 16 }),
+        );
+
+        // Total length leaving no room for the TCP header
+        let mut request = REQUEST;
+        request[7] = 0x1E;
+
+        assert_eq!(
+            Packet::parse(&request).err(),
+            Some(ParseError::Truncated { layer: Layer::Tcp, offset: 24, expected: 20, actual: 10 }),
+        );
+
+        // Data offset of 15 words in a 41 byte segment
+        let mut request = REQUEST;
+        request[36] = 0xF2;
+
+        assert_eq!(
+            Packet::parse(&request).err(),
+            Some(ParseError::Truncated { layer: Layer::Tcp, offset: 24, expected: 60, actual: 41 }),
+        );
+
+        // Data offset below the fixed header
+        let mut request = REQUEST;
+        request[36] = 0x40;
+
+        assert_eq!(
+            Packet::parse(&request).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Tcp, offset: 36, expected: 20, actual: 16 }),
+        );
+
+        // Every value in every position must be rejected or parsed, never panic
+        for index in 0 .. REQUEST.len() {
+            for value in 0 ..= 255 {
+
+                let mut request = REQUEST;
+                request[index] = value;
+
+                if let Ok(packet) = Packet::parse(&request) {
+                    packet.get_bytes();
+                    packet.get_tcp_data();
+
+                    // Fragments carry no TCP header
+                    if let Some(tcp_header) = packet.tcp_header.as_ref() {
+                        tcp_header.get_bytes();
+                    }
+                }
+
+            }
+        }
+
+    }
+
+    #[test]
+    fn test_packet_view() {
+
+        let view = PacketView::parse(&REQUEST).unwrap();
+        let ip_header = view.get_ip_header().unwrap();
+        let tcp_header = view.tcp_header.unwrap();
+
+        assert_eq!(ip_header.version(), 4);
+        assert_eq!(ip_header.total_length(), 61);
+        assert_eq!(ip_header.header_length(), 20);
+        assert_eq!(ip_header.ttl(), 64);
+        assert_eq!(ip_header.source_address(), 0xC0A80032);
+        assert_eq!(ip_header.destination_address(), 0xC0A80002);
+
+        assert_eq!(tcp_header.source_port(), 46046);
+        assert_eq!(tcp_header.destination_port(), 443);
+        assert_eq!(tcp_header.sequence_number(), 2578391819);
+        assert_eq!(tcp_header.data_offset(), 40);
+        assert_eq!(tcp_header.syn(), true);
+        assert_eq!(tcp_header.ack(), false);
+        assert_eq!(tcp_header.window(), 64240);
+        assert_eq!(tcp_header.checksum(), 0xCCC8);
+
+        // Slices point into the caller's buffer
+        assert_eq!(view.get_bytes().as_ptr(), REQUEST.as_ptr());
+        assert_eq!(ip_header.get_bytes().as_ptr(), REQUEST[4 ..].as_ptr());
+        assert_eq!(tcp_header.get_bytes().as_ptr(), REQUEST[24 ..].as_ptr());
+        assert_eq!(view.get_tcp_data(), &[0x40]);
+
+        let packet = view.to_owned();
+        let parsed = Packet::parse(&REQUEST).unwrap();
+
+        assert_eq!(packet.get_bytes(), parsed.get_bytes());
+        assert_eq!(packet.get_ip_header().unwrap().get_bytes(), parsed.get_ip_header().unwrap().get_bytes());
+        assert_eq!(packet.tcp_header.as_ref().unwrap().get_bytes(), parsed.tcp_header.as_ref().unwrap().get_bytes());
+        assert_eq!(packet.tcp_header.as_ref().unwrap().sequence_number, parsed.tcp_header.as_ref().unwrap().sequence_number);
+
+        assert_eq!(PacketView::parse(&REQUEST[.. 64]).err(), Some(ParseError::Truncated { layer: Layer::Ipv4, offset: 4, expected: 61, actual: 60 }));
+
+    }
+
+    #[test]
+    fn test_jumbo_packets() {
+
+        for &length in [8960, 65495].iter() {
+
+            let mut packet_builder = PacketBuilder::new();
+            packet_builder.ip_header_builder.source_address = 0x7F000001;
+            packet_builder.ip_header_builder.destination_address = 0x7F000001;
+            packet_builder.bytes = (0 .. length).map(|index| index as u8).collect();
+
+            let packet = packet_builder.build().unwrap();
+            let parsed = Packet::parse(packet.get_bytes()).unwrap();
+
+            assert_eq!(parsed.get_ip_header().unwrap().total_length as usize, 40 + length);
+            assert_eq!(parsed.get_tcp_data(), &packet_builder.bytes[..]);
+
+        }
+
+    }
+
+    #[test]
+    fn test_tcp_option_parsing() {
+
+        let packet = Packet::parse(&REQUEST).unwrap();
+        let options: Vec<TcpOption> = packet.tcp_header.as_ref().unwrap().get_options().map(Result::unwrap).collect();
+
+        assert_eq!(packet.tcp_header.as_ref().unwrap().options, &REQUEST[44 .. 64]);
+        assert_eq!(options, vec![
+            TcpOption::Mss(1460),
+            TcpOption::SackPermitted,
+            TcpOption::Timestamps { value: 0xC01B8C50, echo_reply: 0 },
+            TcpOption::Nop,
+            TcpOption::WindowScale(7),
+        ]);
+
+        let view = PacketView::parse(&REQUEST).unwrap();
+        assert_eq!(view.tcp_header.as_ref().unwrap().get_options().count(), 5);
+
+        let malformed: [(&[u8], ParseError); 5] = [
+            (&[0x02, 0x03, 0x05, 0x00], ParseError::InvalidOption { layer: Layer::Tcp, offset: 20, kind: 2, length: 3 }),
+            (&[0x01, 0x05, 0x0B, 0x00, 0x00, 0x00, 0x00, 0x00], ParseError::InvalidOption { layer: Layer::Tcp, offset: 21, kind: 5, length: 11 }),
+            (&[0x01, 0x01, 0x01, 0xFE], ParseError::InvalidOption { layer: Layer::Tcp, offset: 23, kind: 254, length: 1 }),
+            (&[0x1E, 0x01, 0x00, 0x00], ParseError::InvalidOption { layer: Layer::Tcp, offset: 20, kind: 30, length: 1 }),
+            (&[0x1E, 0x08, 0x00, 0x00], ParseError::InvalidOption { layer: Layer::Tcp, offset: 20, kind: 30, length: 8 }),
+        ];
+
+        for (options, error) in malformed.iter() {
+
+            let mut packet_builder = PacketBuilder::new();
+            packet_builder.tcp_header_builder.options = vec![TcpOption::Nop; options.len()];
+
+            // Overwrite the NOPs with raw option bytes
+            let mut packet = packet_builder.build().unwrap();
+            packet.tcp_header.as_mut().unwrap().bytes[20 .. 20 + options.len()].copy_from_slice(options);
+
+            let parsed: Vec<_> = packet.tcp_header.as_ref().unwrap().get_options().collect();
+
+            assert_eq!(parsed.last(), Some(&Err(error.clone())));
+
+        }
+
+        // Options after End of Option List are padding
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.tcp_header_builder.options = vec![TcpOption::Nop; 4];
+
+        let mut packet = packet_builder.build().unwrap();
+        packet.tcp_header.as_mut().unwrap().bytes[20 .. 24].copy_from_slice(&[0x04, 0x02, 0x00, 0xFF]);
+
+        let parsed: Vec<_> = packet.tcp_header.as_ref().unwrap().get_options().collect();
+
+        assert_eq!(parsed, vec![Ok(TcpOption::SackPermitted), Ok(TcpOption::EndOfList)]);
+
+    }
+
+    #[test]
+    fn test_tcp_option_building() {
+
+        // Same options as the SYN in REQUEST, NOP alignment must match what Linux sends
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.tcp_header_builder.options = vec![
+            TcpOption::Mss(1460),
+            TcpOption::SackPermitted,
+            TcpOption::Timestamps { value: 0xC01B8C50, echo_reply: 0 },
+            TcpOption::WindowScale(7),
+        ];
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(packet.tcp_header.as_ref().unwrap().data_offset, 40);
+        assert_eq!(&packet.tcp_header.as_ref().unwrap().get_bytes()[20 ..], &REQUEST[44 .. 64]);
+
+        // SACK blocks and timestamps without SACK permitted are preceded by two NOPs
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.tcp_header_builder.options = vec![
+            TcpOption::Timestamps { value: 1, echo_reply: 2 },
+            TcpOption::Sack(vec![(100, 200)]),
+        ];
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.tcp_header.as_ref().unwrap().get_bytes()[20 ..], &[
+            0x01, 0x01, 0x08, 0x0A, 0x00, 0x00, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x02, 0x01, 0x01, 0x05, 0x0A,
+            0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00, 0xC8,
+        ][..]);
+
+        let parsed = Packet::parse(packet.get_bytes()).unwrap();
+        let options: Vec<TcpOption> = parsed.tcp_header.as_ref().unwrap().get_options().map(Result::unwrap).collect();
+
+        assert_eq!(options, vec![
+            TcpOption::Nop,
+            TcpOption::Nop,
+            TcpOption::Timestamps { value: 1, echo_reply: 2 },
+            TcpOption::Nop,
+            TcpOption::Nop,
+            TcpOption::Sack(vec![(100, 200)]),
+        ]);
+
+        // Timestamps with four SACK blocks needs 12 + 36 bytes
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.tcp_header_builder.options = vec![
+            TcpOption::Timestamps { value: 1, echo_reply: 2 },
+            TcpOption::Sack(vec![(1, 2), (3, 4), (5, 6), (7, 8)]),
+        ];
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::OptionsTooLong { layer: Layer::Tcp, max: 40, actual: 48 }));
+
+    }
+
+    #[test]
+    fn test_ip_options() {
+
+        let route = vec![0x0A000001, 0x0A000002, 0x0A000003, 0x0A000004, 0x0A000005, 0x0A000006, 0x0A000007, 0x0A000008];
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.source_address = 0xC0A80032;
+        packet_builder.ip_header_builder.destination_address = 0xC0A80002;
+        packet_builder.ip_header_builder.options = vec![
+            Ipv4Option::RouterAlert(0),
+            Ipv4Option::LooseSourceRoute { pointer: 4, addresses: route.clone() },
+            Ipv4Option::EndOfList,
+        ];
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(packet.get_ip_header().unwrap().header_length, 60);
+        assert_eq!(&packet.get_ip_header().unwrap().get_bytes()[20 .. 27], &[0x94, 0x04, 0x00, 0x00, 0x83, 0x23, 0x04][..]);
+
+        let parsed = Packet::parse(packet.get_bytes()).unwrap();
+        let options: Vec<Ipv4Option> = parsed.get_ip_header().unwrap().get_options().map(Result::unwrap).collect();
+
+        assert_eq!(parsed.get_ip_header().unwrap().options.len(), 40);
+        assert_eq!(options, vec![
+            Ipv4Option::RouterAlert(0),
+            Ipv4Option::LooseSourceRoute { pointer: 4, addresses: route },
+            Ipv4Option::EndOfList,
+        ]);
+        assert_eq!(options[1].is_copied(), true);
+        assert_eq!(options[0].is_copied(), true);
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.options = vec![
+            Ipv4Option::Nop,
+            Ipv4Option::RecordRoute { pointer: 4, addresses: vec![0, 0] },
+            Ipv4Option::Timestamp { pointer: 5, overflow: 2, flag: 0, data: vec![1000] },
+            Ipv4Option::Security { classification: 0xAB, protection_authority: vec![0x80] },
+            Ipv4Option::Unknown { kind: 0x19, data: vec![0x01, 0x02] },
+        ];
+
+        let packet = packet_builder.build().unwrap();
+        let options: Vec<Ipv4Option> = packet.get_ip_header().unwrap().get_options().map(Result::unwrap).collect();
+
+        assert_eq!(options[.. 5], packet_builder.ip_header_builder.options[..]);
+        assert_eq!(options[2].is_copied(), false);
+
+        // Route data not a multiple of an address
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.options = vec![Ipv4Option::Nop; 8];
+
+        let mut packet = packet_builder.build().unwrap();
+        let NetworkHeader::Ipv4(ip_header) = &mut packet.network_header else { panic!() };
+        ip_header.bytes[20 .. 28].copy_from_slice(&[0x01, 0x07, 0x05, 0x04, 0x00, 0x00, 0x00, 0x00]);
+
+        let parsed: Vec<_> = ip_header.get_options().collect();
+
+        assert_eq!(parsed, vec![
+            Ok(Ipv4Option::Nop),
+            Err(ParseError::InvalidOption { layer: Layer::Ipv4, offset: 21, kind: 7, length: 5 }),
+        ]);
+
+    }
+
+    #[test]
+    fn test_ip_header_fields() {
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.source_address = 0xC0A80032;
+        packet_builder.ip_header_builder.destination_address = 0xC0A80002;
+        packet_builder.ip_header_builder.dscp = 46;
+        packet_builder.ip_header_builder.ecn = 0b10;
+        packet_builder.ip_header_builder.identification = Identification::Fixed(0xBEEF);
+        packet_builder.ip_header_builder.dont_fragment = false;
+        packet_builder.ip_header_builder.more_fragments = true;
+        packet_builder.ip_header_builder.fragment_offset = 0x1234;
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.get_ip_header().unwrap().get_bytes()[.. 8], &[0x45, 0xBA, 0x00, 0x28, 0xBE, 0xEF, 0x32, 0x34][..]);
+
+        let parsed = Packet::parse(packet.get_bytes()).unwrap();
+
+        assert_eq!(parsed.get_ip_header().unwrap().dscp, 46);
+        assert_eq!(parsed.get_ip_header().unwrap().ecn, 0b10);
+        assert_eq!(parsed.get_ip_header().unwrap().identification, 0xBEEF);
+        assert_eq!(parsed.get_ip_header().unwrap().dont_fragment, false);
+        assert_eq!(parsed.get_ip_header().unwrap().more_fragments, true);
+        assert_eq!(parsed.get_ip_header().unwrap().fragment_offset, 0x1234);
+        assert_eq!(parsed.get_ip_header().unwrap().header_checksum, IPHeader::calculate_checksum(parsed.get_ip_header().unwrap().get_bytes()));
+
+        // Counters are kept per destination
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.identification = Identification::per_destination();
+        packet_builder.ip_header_builder.dont_fragment = true;
+
+        let mut identifications = Vec::new();
+
+        for &destination_address in [1, 1, 2, 1, 2].iter() {
+            packet_builder.ip_header_builder.destination_address = destination_address;
+            identifications.push(packet_builder.build().unwrap().get_ip_header().unwrap().identification);
+        }
+
+        assert_eq!(identifications, vec![0, 1, 0, 2, 1]);
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.identification = Identification::random();
+
+        let first = packet_builder.build().unwrap().get_ip_header().unwrap().identification;
+        let differs = (0 .. 16).any(|_| packet_builder.build().unwrap().get_ip_header().unwrap().identification != first);
+
+        assert_eq!(differs, true);
+
+        // Forgotten destinations restart from zero, the map stays bounded
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.identification = Identification::per_destination();
+
+        for destination_address in 0 ..= PER_DESTINATION_LIMIT as u32 {
+            packet_builder.ip_header_builder.destination_address = destination_address;
+            packet_builder.build().unwrap();
+        }
+
+        if let Identification::PerDestination(counters) = &packet_builder.ip_header_builder.identification {
+            assert_eq!(counters.lock().unwrap().len(), PER_DESTINATION_LIMIT);
+        }
+
+        // Builders can be shared between threads
+        fn is_sync<T: Sync>(_: &T) -> bool { true }
+
+        assert_eq!(is_sync(&packet_builder), true);
+
+        // DSCP is 6 bits, the built header agrees with its bytes
+        packet_builder.ip_header_builder.dscp = 0xFF;
+        packet_builder.ip_header_builder.ecn = 0;
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(packet.get_ip_header().unwrap().get_bytes()[1], 0xFC);
+        assert_eq!(packet.get_ip_header().unwrap().dscp, 0x3F);
+
+    }
+
+    #[test]
+    fn test_tcp_flags() {
+
+        // ECN setup SYN
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.tcp_header_builder.set_flags(TcpFlags::SYN | TcpFlags::ECE | TcpFlags::CWR);
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.tcp_header.as_ref().unwrap().get_bytes()[12 .. 14], &[0x50, 0xC2][..]);
+        assert_eq!(packet.tcp_header.as_ref().unwrap().get_flags(), TcpFlags::SYN | TcpFlags::ECE | TcpFlags::CWR);
+
+        // AccECN SYN with every reserved bit set
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.tcp_header_builder.syn = true;
+        packet_builder.tcp_header_builder.ns = true;
+        packet_builder.tcp_header_builder.cwr = true;
+        packet_builder.tcp_header_builder.ece = true;
+        packet_builder.tcp_header_builder.reserved = 0b111;
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.tcp_header.as_ref().unwrap().get_bytes()[12 .. 14], &[0x5F, 0xC2][..]);
+
+        let parsed = Packet::parse(packet.get_bytes()).unwrap();
+        let view = PacketView::parse(packet.get_bytes()).unwrap();
+
+        assert_eq!(parsed.tcp_header.as_ref().unwrap().ns, true);
+        assert_eq!(parsed.tcp_header.as_ref().unwrap().cwr, true);
+        assert_eq!(parsed.tcp_header.as_ref().unwrap().ece, true);
+        assert_eq!(parsed.tcp_header.as_ref().unwrap().reserved, 0b111);
+        assert_eq!(view.tcp_header.as_ref().unwrap().ns(), true);
+        assert_eq!(view.tcp_header.as_ref().unwrap().reserved(), 0b111);
+
+        let flags = parsed.tcp_header.as_ref().unwrap().get_flags();
+
+        assert_eq!(flags, view.tcp_header.as_ref().unwrap().get_flags());
+        assert_eq!(flags.bits(), 0xFC2);
+        assert_eq!(flags.contains(TcpFlags::RESERVED | TcpFlags::NS | TcpFlags::SYN), true);
+        assert_eq!(flags.contains(TcpFlags::ACK), false);
+        assert_eq!(format!("{:?}", flags), "TcpFlags(RESERVED(0x7) | NS | CWR | ECE | SYN)");
+        assert_eq!(format!("{:?}", !flags), "TcpFlags(URG | ACK | PSH | RST | FIN)");
+
+        let request = Packet::parse(&REQUEST).unwrap();
+        assert_eq!(request.tcp_header.as_ref().unwrap().get_flags(), TcpFlags::SYN);
+
+    }
+
+    #[test]
+    fn test_validation_policy() {
+
+        let strict = ParseOptions::new(ValidationPolicy::Strict);
+        let lenient = ParseOptions::from(ValidationPolicy::Lenient);
+        let none = ParseOptions::new(ValidationPolicy::None);
+
+        assert!(Packet::parse_with(&REQUEST, &strict).is_ok());
+
+        // Damaged IP header checksum is only noticed when verified
+        let mut request = REQUEST;
+        request[15] = 0x0E;
+
+        assert!(Packet::parse(&request).is_ok());
+        assert_eq!(
+            Packet::parse_with(&request, &strict).err(),
+            Some(ParseError::ChecksumMismatch { layer: Layer::Ipv4, offset: 14, expected: 0xD80D, actual: 0xD80E }),
+        );
+
+        // Damaged data decodes unless TCP checksum is verified
+        let mut request = REQUEST;
+        request[64] = 0x41;
+
+        assert!(Packet::parse_with(&request, &strict).is_err());
+        assert_eq!(Packet::parse_with(&request, &lenient).unwrap().get_tcp_data(), &[0x41]);
+
+        // IP version
+        let mut request = REQUEST;
+        request[4] = 0x55;
+
+        assert!(Packet::parse(&request).is_ok());
+        assert!(Packet::parse_with(&request, &none).is_ok());
+        assert_eq!(
+            Packet::parse_with(&request, &lenient).err(),
+            Some(ParseError::UnsupportedVersion { layer: Layer::Ipv4, offset: 4, expected: 4, actual: 5 }),
+        );
+
+        // TCP reserved bits and IP reserved flag
+        let mut request = REQUEST;
+        request[36] = 0xA4;
+
+        assert!(Packet::parse_with(&request, &lenient).is_ok());
+        assert_eq!(
+            Packet::parse_with(&request, &strict).err(),
+            Some(ParseError::ReservedBitsSet { layer: Layer::Tcp, offset: 36, actual: 0b010 }),
+        );
+
+        let mut request = REQUEST;
+        request[10] = 0xC0;
+
+        assert!(Packet::parse_with(&request, &lenient).is_ok());
+        assert_eq!(
+            Packet::parse_with(&request, &strict).err(),
+            Some(ParseError::ReservedBitsSet { layer: Layer::Ipv4, offset: 10, actual: 1 }),
+        );
+
+        // Total length beyond the capture falls back to the buffer length
+        let mut request = REQUEST;
+        request[7] = 0x80;
+
+        assert!(Packet::parse_with(&request, &lenient).is_err());
+
+        let packet = Packet::parse_with(&request, &none).unwrap();
+
+        assert_eq!(packet.get_ip_header().unwrap().total_length, 0x80);
+        assert_eq!(packet.get_bytes(), &request[..]);
+        assert_eq!(packet.get_tcp_data(), &[0x40]);
+
+        // Total length shorter than the header
+        let mut request = REQUEST;
+        request[7] = 0x04;
+
+        assert_eq!(PacketView::parse_with(&request, &none).unwrap().get_tcp_data(), &[0x40]);
+        assert_eq!(PacketView::parse_with(&request, &none).unwrap().get_ip_header().unwrap().get_data_length(), 0);
+        assert_eq!(Packet::parse_with(&request, &none).unwrap().get_ip_header().unwrap().get_data_length(), 0);
+
+        // Nothing panics with every check disabled
+        for index in 0 .. REQUEST.len() {
+            for value in 0 ..= 255 {
+
+                let mut request = REQUEST;
+                request[index] = value;
+
+                if let Ok(packet) = Packet::parse_with(&request, &none) {
+                    packet.get_bytes();
+                    packet.get_tcp_data();
+                    packet.tcp_header.as_ref().map(|tcp_header| tcp_header.get_options().count());
+                    packet.get_ip_header().unwrap().get_options().count();
+                    packet.get_ip_header().unwrap().get_data_length();
+                }
+
+            }
+        }
+
+    }
+
+    #[test]
+    fn test_tun_framing() {
+
+        let raw = ParseOptions { framing: Framing::NoPacketInfo, ..ParseOptions::default() };
+        let auto = ParseOptions { framing: Framing::Auto, ..ParseOptions::default() };
+
+        let packet = Packet::parse_with(&REQUEST[4 ..], &raw).unwrap();
+
+        assert_eq!(packet.link_layer, LinkLayer::None);
+        assert_eq!(packet.get_ip_offset(), 0);
+        assert_eq!(packet.get_bytes(), &REQUEST[4 ..]);
+        assert_eq!(packet.get_tcp_data(), &[0x40]);
+
+        // Raw packets are not mistaken for packet info and the other way around
+        let packet = Packet::parse_with(&REQUEST[4 ..], &auto).unwrap();
+        assert_eq!(packet.link_layer, LinkLayer::None);
+
+        let packet = Packet::parse_with(&REQUEST, &auto).unwrap();
+        assert_eq!(packet.link_layer, LinkLayer::Tun(TunHeader { flags: 0, proto: 0x0800 }));
+        assert_eq!(packet.get_bytes(), &REQUEST[..]);
+
+        assert_eq!(
+            Packet::parse_with(&REQUEST, &raw).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Ipv4, offset: 0, expected: 20, actual: 0 }),
+        );
+
+        // Packet info flags are kept
+        let mut request = REQUEST;
+        request[1] = TUN_PKT_STRIP as u8;
+
+        let packet = Packet::parse(&request).unwrap();
+
+        assert_eq!(packet.link_layer, LinkLayer::Tun(TunHeader { flags: TUN_PKT_STRIP, proto: 0x0800 }));
+        assert_eq!(TunHeader::parse(&request).unwrap().is_stripped(), true);
+        assert_eq!(TunHeader::parse(&REQUEST).unwrap().is_stripped(), false);
+        assert_eq!(packet.get_bytes(), &request[..]);
+
+        // Building without packet info
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::NoPacketInfo;
+        packet_builder.bytes.push(0x40);
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(packet.get_bytes()[0], 0x45);
+        assert_eq!(packet.get_bytes().len(), 41);
+        assert_eq!(packet.get_tcp_data(), &[0x40]);
+        assert_eq!(Packet::parse_with(packet.get_bytes(), &auto).unwrap().get_tcp_data(), &[0x40]);
+
+    }
+
+    #[test]
+    fn test_ethernet_frames() {
+
+        let ethernet = ParseOptions { framing: Framing::Ethernet, ..ParseOptions::default() };
+
+        let mut frame = vec![
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00,         // Ethernet header
+            0x00, 0x00, 0x00, 0x01, 0x08, 0x00,
+        ];
+        frame.extend_from_slice(&REQUEST[4 ..]);
+
+        let packet = Packet::parse_with(&frame, &ethernet).unwrap();
+
+        assert_eq!(packet.link_layer, LinkLayer::Ethernet(EthernetHeader {
+            destination_address: MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]),
+            source_address: MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]),
+            vlan_tags: Vec::new(),
+            ether_type: 0x0800,
+        }));
+        assert_eq!(packet.get_ip_offset(), 14);
+        assert_eq!(packet.get_ip_header().unwrap().source_address, 0xC0A80032);
+        assert_eq!(packet.tcp_header.as_ref().unwrap().destination_port, 443);
+        assert_eq!(packet.get_tcp_data(), &[0x40]);
+
+        // Build the same frame back
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::Ethernet;
+        packet_builder.ethernet_header_builder.destination_address = MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]);
+        packet_builder.ethernet_header_builder.source_address = MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        packet_builder.ip_header_builder.source_address = 0xC0A80032;
+        packet_builder.ip_header_builder.destination_address = 0xC0A80002;
+        packet_builder.bytes.push(0x40);
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.get_bytes()[.. 14], &frame[.. 14]);
+        assert_eq!(Packet::parse_with(packet.get_bytes(), &ethernet).unwrap().get_tcp_data(), &[0x40]);
+
+        // Minimum size padding is dropped
+        let mut padded = packet.get_bytes().to_vec();
+        padded.resize(60, 0);
+
+        let view = PacketView::parse_with(&padded, &ethernet).unwrap();
+
+        assert_eq!(view.get_bytes(), packet.get_bytes());
+        assert_eq!(view.get_tcp_data(), &[0x40]);
+
+        // LLDP
+        frame[12] = 0x88;
+        frame[13] = 0xCC;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &ethernet).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Ethernet, offset: 12, expected: 0x0800, actual: 0x88CC }),
+        );
+
+        assert_eq!(
+            Packet::parse_with(&frame[.. 13], &ethernet).err(),
+            Some(ParseError::Truncated { layer: Layer::Ethernet, offset: 0, expected: 14, actual: 13 }),
+        );
+
+    }
+
+    #[test]
+    fn test_vlan_tags() {
+
+        let ethernet = ParseOptions { framing: Framing::Ethernet, ..ParseOptions::default() };
+
+        // Service tag 100 over customer tag 10 with priority 5
+        let mut frame = vec![
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00,         // Ethernet header
+            0x00, 0x00, 0x00, 0x01,
+            0x88, 0xA8, 0x00, 0x64,                                 // 802.1ad tag
+            0x81, 0x00, 0xB0, 0x0A,                                 // 802.1Q tag
+            0x08, 0x00,
+        ];
+        frame.extend_from_slice(&REQUEST[4 ..]);
+
+        let packet = Packet::parse_with(&frame, &ethernet).unwrap();
+
+        assert_eq!(packet.get_vlan_tags(), &[
+            VlanTag { tpid: TPID_8021AD, pcp: 0, dei: false, vid: 100 },
+            VlanTag { tpid: TPID_8021Q, pcp: 5, dei: true, vid: 10 },
+        ][..]);
+        assert_eq!(packet.get_ip_offset(), 22);
+        assert_eq!(packet.get_tcp_data(), &[0x40]);
+        assert_eq!(packet.get_bytes(), &frame[..]);
+
+        // Push customer tag, then service tag on the outside
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::Ethernet;
+        packet_builder.ethernet_header_builder.destination_address = MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]);
+        packet_builder.ethernet_header_builder.source_address = MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        packet_builder.ethernet_header_builder.push_vlan_tag(VlanTag { pcp: 5, dei: true, ..VlanTag::new(10) });
+        packet_builder.ethernet_header_builder.push_vlan_tag(VlanTag::service(100));
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.get_bytes()[.. 22], &frame[.. 22]);
+
+        // Pop the service tag
+        assert_eq!(packet_builder.ethernet_header_builder.pop_vlan_tag(), Some(VlanTag::service(100)));
+
+        let packet = packet_builder.build().unwrap();
+        let parsed = PacketView::parse_with(packet.get_bytes(), &ethernet).unwrap();
+
+        assert_eq!(parsed.link_layer.get_vlan_tags(), &[VlanTag { pcp: 5, dei: true, ..VlanTag::new(10) }][..]);
+        assert_eq!(parsed.get_ip_offset(), 18);
+
+        assert_eq!(packet_builder.ethernet_header_builder.pop_vlan_tag().map(|tag| tag.vid), Some(10));
+        assert_eq!(packet_builder.ethernet_header_builder.pop_vlan_tag(), None);
+
+        // Tag without the EtherType behind it
+        assert_eq!(
+            Packet::parse_with(&frame[.. 20], &ethernet).err(),
+            Some(ParseError::Truncated { layer: Layer::Ethernet, offset: 0, expected: 22, actual: 20 }),
+        );
+
+    }
+
+    #[test]
+    fn test_arp() {
+
+        let ethernet = ParseOptions { framing: Framing::Ethernet, ..ParseOptions::default() };
+        let requester: MacAddress = "02:00:00:00:00:01".parse().unwrap();
+        let responder: MacAddress = "02:00:00:00:00:02".parse().unwrap();
+
+        // Broadcast who-has 192.168.0.1 tell 192.168.0.50
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::Ethernet;
+        packet_builder.ethernet_header_builder.destination_address = MacAddress::BROADCAST;
+        packet_builder.ethernet_header_builder.source_address = requester;
+
+        let request = packet_builder.build_arp(&ArpPacket::request(requester, 0xC0A80032, 0xC0A80001)).unwrap();
+
+        assert_eq!(request.get_bytes(), &[
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06,
+            0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01,
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0xC0, 0xA8, 0x00, 0x32,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0xA8, 0x00, 0x01,
+        ]);
+
+        // Padded to the Ethernet minimum
+        let mut frame = request.get_bytes().to_vec();
+        frame.resize(60, 0);
+
+        let parsed = Packet::parse_with(&frame, &ethernet).unwrap();
+        let arp_packet = parsed.get_arp_packet().unwrap();
+
+        assert_eq!(parsed.get_bytes(), request.get_bytes());
+        assert_eq!(parsed.get_ip_header().is_none(), true);
+        assert_eq!(parsed.tcp_header.is_none(), true);
+        assert_eq!(parsed.get_tcp_data(), &[] as &[u8]);
+        assert_eq!(arp_packet.opcode, ARP_REQUEST);
+        assert_eq!(arp_packet.sender_hardware_address, requester);
+        assert_eq!(arp_packet.target_protocol_address, 0xC0A80001);
+
+        let view = PacketView::parse_with(&frame, &ethernet).unwrap();
+
+        assert_eq!(view.get_arp_packet(), Some(arp_packet));
+        assert_eq!(view.get_bytes(), request.get_bytes());
+
+        // Only requests for our own address are answered
+        assert_eq!(parsed.arp_reply(0xC0A80002, responder).is_none(), true);
+
+        let reply = parsed.arp_reply(0xC0A80001, responder).unwrap();
+        let arp_packet = reply.get_arp_packet().unwrap();
+
+        assert_eq!(&reply.get_bytes()[.. 14], &[0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x08, 0x06]);
+        assert_eq!(arp_packet.opcode, ARP_REPLY);
+        assert_eq!(arp_packet.sender_hardware_address, responder);
+        assert_eq!(arp_packet.sender_protocol_address, 0xC0A80001);
+        assert_eq!(arp_packet.target_hardware_address, requester);
+        assert_eq!(arp_packet.target_protocol_address, 0xC0A80032);
+        assert_eq!(reply.arp_reply(0xC0A80001, responder).is_none(), true);
+
+        // Round trip through the parser
+        let parsed = Packet::parse_with(reply.get_bytes(), &ethernet).unwrap();
+
+        assert_eq!(parsed.get_arp_packet(), Some(arp_packet));
+
+        // Over TUN packet info
+        let request = PacketBuilder::new().build_arp(&ArpPacket::request(requester, 0xC0A80032, 0xC0A80001)).unwrap();
+
+        assert_eq!(&request.get_bytes()[.. 4], &[0x00, 0x00, 0x08, 0x06]);
+        assert_eq!(Packet::parse(request.get_bytes()).unwrap().get_arp_packet().is_some(), true);
+
+        // Malformed
+        let mut bytes = request.get_bytes().to_vec();
+        bytes[8] = 0x08;
+
+        assert_eq!(
+            Packet::parse(&bytes).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Arp, offset: 8, expected: 6, actual: 8 }),
+        );
+
+        assert_eq!(
+            Packet::parse(&bytes[.. 20]).err(),
+            Some(ParseError::Truncated { layer: Layer::Arp, offset: 4, expected: 28, actual: 16 }),
+        );
+
+        bytes[4 .. 6].copy_from_slice(&[0x00, 0x06]);
+
+        assert_eq!(
+            Packet::parse(&bytes).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Arp, offset: 4, expected: 1, actual: 6 }),
+        );
+
+    }
+
+    #[test]
+    fn test_virtio_net() {
+
+        let virtio_net = ParseOptions { framing: Framing::VirtioNet, ..ParseOptions::default() };
+        let strict = ParseOptions { framing: Framing::VirtioNet, ..ParseOptions::new(ValidationPolicy::Strict) };
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::VirtioNet;
+        packet_builder.ip_header_builder.source_address = 0xC0A80032;
+        packet_builder.ip_header_builder.destination_address = 0xC0A80001;
+        packet_builder.ip_header_builder.identification = Identification::Fixed(0x1000);
+        packet_builder.tcp_header_builder.sequence_number = 0xFFFFFC00;
+        packet_builder.tcp_header_builder.set_flags(TcpFlags::ACK | TcpFlags::PSH | TcpFlags::FIN | TcpFlags::CWR);
+        packet_builder.bytes = (0 .. 2500).map(|i| i as u8).collect();
+
+        // Plain header, finished checksum
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.get_bytes()[.. 10], &[0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(Packet::parse_with(packet.get_bytes(), &strict).is_ok(), true);
+
+        // Super-packet with checksum offload
+        packet_builder.virtio_net_header.flags = VIRTIO_NET_HDR_F_NEEDS_CSUM;
+        packet_builder.virtio_net_header.gso_type = VIRTIO_NET_HDR_GSO_TCPV4;
+        packet_builder.virtio_net_header.gso_size = 1000;
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.get_bytes()[.. 10], &[0x01, 0x01, 0x28, 0x00, 0xE8, 0x03, 0x14, 0x00, 0x10, 0x00]);
+
+        let parsed = Packet::parse_with(packet.get_bytes(), &virtio_net).unwrap();
+        let virtio_net_header = parsed.get_virtio_net_header().unwrap();
+
+        assert_eq!(virtio_net_header.needs_checksum(), true);
+        assert_eq!(virtio_net_header.is_tcp_gso(), true);
+        assert_eq!(virtio_net_header.header_length, 40);
+        assert_eq!(virtio_net_header.gso_size, 1000);
+        assert_eq!(parsed.get_ip_offset(), 10);
+        assert_eq!(parsed.get_tcp_data().len(), 2500);
+
+        // Same bytes behind a plain header carry a bad checksum
+        let mut bytes = packet.get_bytes().to_vec();
+        bytes[0] = 0;
+
+        assert_eq!(
+            Packet::parse_with(&bytes, &virtio_net).err().map(|error| error.offset()),
+            Some(46),
+        );
+
+        let segments = parsed.segment().unwrap();
+
+        assert_eq!(segments.len(), 3);
+
+        for (index, segment) in segments.iter().enumerate() {
+
+            let ip_header = segment.get_ip_header().unwrap();
+            let tcp_header = segment.tcp_header.as_ref().unwrap();
+            let length = if index < 2 { 1000 } else { 500 };
+
+            assert_eq!(Packet::parse_with(segment.get_bytes(), &strict).is_ok(), true);
+            assert_eq!(segment.get_virtio_net_header(), Some(&VirtioNetHeader::new()));
+            assert_eq!(ip_header.total_length as usize, 40 + length);
+            assert_eq!(ip_header.identification, 0x1000 + index as u16);
+            assert_eq!(tcp_header.sequence_number, 0xFFFFFC00u32.wrapping_add(index as u32 * 1000));
+            assert_eq!(tcp_header.cwr, index == 0);
+            assert_eq!(tcp_header.psh, index == 2);
+            assert_eq!(tcp_header.fin, index == 2);
+            assert_eq!(tcp_header.ack, true);
+            assert_eq!(segment.get_tcp_data(), &packet_builder.bytes[index * 1000 .. index * 1000 + length]);
+
+        }
+
+        // MTU applies per segment
+        packet_builder.mtu = Some(1040);
+
+        assert_eq!(packet_builder.build().is_ok(), true);
+
+        packet_builder.mtu = Some(1039);
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::ExceedsMtu { mtu: 1039, actual: 1040 }));
+
+        // Not a super-packet, checksum gets finished
+        packet_builder.mtu = None;
+        packet_builder.virtio_net_header.gso_type = VIRTIO_NET_HDR_GSO_NONE;
+
+        let packet = packet_builder.build().unwrap();
+        let segments = Packet::parse_with(packet.get_bytes(), &virtio_net).unwrap().segment().unwrap();
+
+        packet_builder.framing = Framing::NoPacketInfo;
+
+        assert_eq!(segments.len(), 1);
+        assert_eq!(&segments[0].get_bytes()[10 ..], packet_builder.build().unwrap().get_bytes());
+
+        assert_eq!(
+            Packet::parse_with(&[0x01, 0x00, 0x00], &virtio_net).err(),
+            Some(ParseError::Truncated { layer: Layer::VirtioNet, offset: 0, expected: 10, actual: 3 }),
+        );
+
+    }
+
+    #[test]
+    fn test_cooked_capture() {
+
+        let ip_packet = &REQUEST[4 ..];
+
+        // Received from another host on eth0
+        let mut frame = vec![
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x06, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x08, 0x00,
+        ];
+        frame.extend_from_slice(ip_packet);
+
+        let sll = ParseOptions { framing: Framing::Sll, ..ParseOptions::default() };
+        let packet = Packet::parse_with(&frame, &sll).unwrap();
+
+        let LinkLayer::Sll(sll_header) = &packet.link_layer else { panic!() };
+
+        assert_eq!(sll_header.packet_type, LINUX_SLL_HOST);
+        assert_eq!(sll_header.arphrd_type, ARPHRD_ETHER);
+        assert_eq!(sll_header.get_address(), &[0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(sll_header.protocol, 0x0800);
+        assert_eq!(sll_header.get_bytes(), frame[.. 16]);
+        assert_eq!(packet.get_ip_offset(), 16);
+        assert_eq!(packet.get_ip_header().unwrap().source_address, 0xC0A80032);
+        assert_eq!(packet.get_tcp_data(), &[0x40]);
+
+        // Same packet as SLL2 on interface 3
+        let mut frame = vec![
+            0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x01, 0x00, 0x06,
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+        ];
+        frame.extend_from_slice(ip_packet);
+
+        let sll2 = ParseOptions { framing: Framing::Sll2, ..ParseOptions::default() };
+        let view = PacketView::parse_with(&frame, &sll2).unwrap();
+
+        let LinkLayer::Sll2(sll2_header) = &view.link_layer else { panic!() };
+
+        assert_eq!(sll2_header.interface_index, 3);
+        assert_eq!(sll2_header.packet_type, LINUX_SLL_HOST);
+        assert_eq!(sll2_header.get_address(), &[0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(sll2_header.get_bytes(), frame[.. 20]);
+        assert_eq!(view.get_ip_offset(), 20);
+        assert_eq!(view.get_tcp_data(), &[0x40]);
+
+        // Built packets go out with a bare header
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::Sll2;
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(packet.link_layer, LinkLayer::Sll2(Sll2Header::new(0x0800)));
+        assert_eq!(packet.get_bytes()[10], LINUX_SLL_OUTGOING);
+        assert_eq!(Packet::parse_with(packet.get_bytes(), &sll2).is_ok(), true);
+
+        // Protocol offset differs between versions
+        frame[0] = 0x88;
+        frame[1] = 0xCC;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &sll2).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Sll, offset: 0, expected: 0x0800, actual: 0x88CC }),
+        );
+
+        assert_eq!(
+            Packet::parse_with(&frame, &sll).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Sll, offset: 14, expected: 0x0800, actual: 0x0000 }),
+        );
+
+        assert_eq!(
+            Packet::parse_with(&frame[.. 19], &sll2).err(),
+            Some(ParseError::Truncated { layer: Layer::Sll, offset: 0, expected: 20, actual: 19 }),
+        );
+
+    }
+
+    #[test]
+    fn test_link_layers() {
+
+        let mut packet = Packet::parse(&REQUEST).unwrap();
+        let ip_bytes = REQUEST[4 ..].to_vec();
+
+        let ethernet_header = EthernetHeader {
+            destination_address: MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]),
+            source_address: MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]),
+            vlan_tags: vec![VlanTag::new(100)],
+            ether_type: 0,
+        };
+
+        let link_layers = vec![
+            LinkLayer::None,
+            LinkLayer::Ethernet(ethernet_header),
+            LinkLayer::VirtioNet(VirtioNetHeader::new()),
+            LinkLayer::Sll(SllHeader::new(0)),
+            LinkLayer::Sll2(Sll2Header::new(0)),
+            LinkLayer::Tun(TunHeader::new(0)),
+        ];
+
+        for link_layer in link_layers {
+
+            let length = link_layer.get_length();
+
+            packet.set_link_layer(link_layer);
+
+            let options = ParseOptions { framing: packet.link_layer.get_framing(), ..ParseOptions::default() };
+            let parsed = Packet::parse_with(packet.get_bytes(), &options).unwrap();
+
+            assert_eq!(packet.link_layer.get_ether_type(), 0x0800);
+            assert_eq!(packet.get_ip_offset(), length);
+            assert_eq!(&packet.get_bytes()[length ..], &ip_bytes[..]);
+            assert_eq!(parsed.link_layer, packet.link_layer);
+            assert_eq!(parsed.get_tcp_data(), &[0x40]);
+
+        }
+
+        assert_eq!(packet.get_bytes(), &REQUEST);
+
+        // Protocol fields follow the payload
+        let mut packet = PacketBuilder::new().build_arp(&ArpPacket::request(MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]), 0xC0A80032, 0xC0A80001)).unwrap();
+        packet.set_link_layer(LinkLayer::Sll(SllHeader::new(0x0800)));
+
+        assert_eq!(packet.link_layer.get_ether_type(), 0x0806);
+        assert_eq!(&packet.get_bytes()[14 .. 16], &[0x08, 0x06]);
+
+        let link_layer = LinkLayer::parse(packet.get_bytes(), Framing::Sll).unwrap();
+
+        assert_eq!(link_layer, packet.link_layer);
+        assert_eq!(link_layer.get_layer(), Layer::Sll);
+        assert_eq!(link_layer.get_ether_type_offset(), 14);
+        assert_eq!(LinkLayer::parse(&REQUEST[4 ..], Framing::Auto).unwrap(), LinkLayer::None);
+        assert_eq!(LinkLayer::parse(&REQUEST, Framing::Auto).unwrap(), LinkLayer::Tun(TunHeader::new(0x0800)));
+
+    }
+
+    #[test]
+    fn test_mac_address() {
+
+        let address: MacAddress = "52:54:00:AB:cd:0F".parse().unwrap();
+
+        assert_eq!(address.octets(), [0x52, 0x54, 0x00, 0xAB, 0xCD, 0x0F]);
+        assert_eq!(address.to_string(), "52:54:00:ab:cd:0f");
+        assert_eq!(format!("{:?}", address), "MacAddress(52:54:00:ab:cd:0f)");
+        assert_eq!(address.is_unicast(), true);
+        assert_eq!(address.is_multicast(), false);
+        assert_eq!(address.is_locally_administered(), true);
+        assert_eq!(address.get_interface_identifier(), [0x50, 0x54, 0x00, 0xFF, 0xFE, 0xAB, 0xCD, 0x0F]);
+
+        let address = MacAddress::from([0x00, 0x1B, 0x21, 0x3A, 0x4C, 0x5D]);
+
+        assert_eq!(address.is_locally_administered(), false);
+        assert_eq!(address.get_interface_identifier(), [0x02, 0x1B, 0x21, 0xFF, 0xFE, 0x3A, 0x4C, 0x5D]);
+        assert_eq!(<[u8; 6]>::from(address), [0x00, 0x1B, 0x21, 0x3A, 0x4C, 0x5D]);
+
+        // IPv4 and IPv6 multicast groups
+        assert_eq!(MacAddress::new([0x01, 0x00, 0x5E, 0x00, 0x00, 0xFB]).is_multicast(), true);
+        assert_eq!(MacAddress::new([0x33, 0x33, 0x00, 0x00, 0x00, 0x01]).is_broadcast(), false);
+        assert_eq!(MacAddress::BROADCAST.is_multicast(), true);
+        assert_eq!(MacAddress::BROADCAST.is_broadcast(), true);
+        assert_eq!(MacAddress::default().is_unspecified(), true);
+        assert_eq!("ff:ff:ff:ff:ff:ff".parse(), Ok(MacAddress::BROADCAST));
+
+        for input in ["", "52:54:00:ab:cd", "52:54:00:ab:cd:0f:00", "52-54-00-ab-cd-0f", "52:54:00:ab:cd:0", "52:54:00:ab:cd:+f", "52:54:00:ab:cd:0g"] {
+            assert_eq!(input.parse::<MacAddress>(), Err(AddressParseError { input: input.to_string() }));
+        }
+
+        // Carried by Ethernet and ARP
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::Ethernet;
+        packet_builder.ethernet_header_builder.source_address = address;
+
+        let packet = packet_builder.build_arp(&ArpPacket::request(address, 0xC0A80032, 0xC0A80001)).unwrap();
+
+        let LinkLayer::Ethernet(ethernet_header) = &packet.link_layer else { panic!() };
+
+        assert_eq!(&packet.get_bytes()[6 .. 12], &address.octets());
+        assert_eq!(&packet.get_bytes()[22 .. 28], &address.octets());
+        assert_eq!(ethernet_header.destination_address.is_unspecified(), true);
+
+    }
+
+    #[test]
+    fn test_pppoe() {
+
+        let ethernet = ParseOptions { framing: Framing::Ethernet, ..ParseOptions::default() };
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::Ethernet;
+        packet_builder.ethernet_header_builder.push_vlan_tag(VlanTag::new(100));
+        packet_builder.pppoe_header = Some(PppoeHeader::new(0x1234));
+        packet_builder.bytes = vec![0x40];
+
+        let packet = packet_builder.build().unwrap();
+
+        let LinkLayer::Pppoe(ethernet_header, pppoe_header) = &packet.link_layer else { panic!() };
+
+        assert_eq!(ethernet_header.ether_type, ETHER_TYPE_PPPOE_SESSION);
+        assert_eq!(pppoe_header.get_payload_length(), 41);
+        assert_eq!(packet.get_ip_offset(), 26);
+        assert_eq!(&packet.get_bytes()[12 .. 26], &[0x81, 0x00, 0x00, 0x64, 0x88, 0x64, 0x11, 0x00, 0x12, 0x34, 0x00, 0x2B, 0x00, 0x21]);
+
+        // Padded to the Ethernet minimum
+        let mut frame = packet.get_bytes().to_vec();
+        frame.resize(80, 0);
+
+        let parsed = Packet::parse_with(&frame, &ethernet).unwrap();
+
+        assert_eq!(parsed.link_layer, packet.link_layer);
+        assert_eq!(parsed.get_vlan_tags(), &[VlanTag::new(100)]);
+        assert_eq!(parsed.get_tcp_data(), &[0x40]);
+        assert_eq!(IPHeader::parse(&frame[parsed.get_ip_offset() ..]).unwrap().total_length, 41);
+
+        // Link control instead of IPv4
+        frame[24 .. 26].copy_from_slice(&PPP_LCP.to_be_bytes());
+
+        assert_eq!(
+            Packet::parse_with(&frame, &ethernet).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Pppoe, offset: 24, expected: 0x0021, actual: 0xC021 }),
+        );
+
+        frame[18] = 0x21;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &ethernet).err(),
+            Some(ParseError::UnsupportedVersion { layer: Layer::Pppoe, offset: 18, expected: 0x11, actual: 0x21 }),
+        );
+
+        assert_eq!(
+            Packet::parse_with(&frame[.. 22], &ethernet).err(),
+            Some(ParseError::Truncated { layer: Layer::Pppoe, offset: 18, expected: 8, actual: 4 }),
+        );
+
+        // Re-encapsulate a TUN packet
+        let mut packet = Packet::parse(&REQUEST).unwrap();
+        packet.set_link_layer(LinkLayer::Pppoe(EthernetHeaderBuilder::new().build(ETHER_TYPE_PPPOE_SESSION), PppoeHeader::new(7)));
+
+        assert_eq!(&packet.get_bytes()[14 .. 22], &[0x11, 0x00, 0x00, 0x07, 0x00, 0x3F, 0x00, 0x21]);
+        assert_eq!(&packet.get_bytes()[22 ..], &REQUEST[4 ..]);
+        assert_eq!(Packet::parse_with(packet.get_bytes(), &ethernet).unwrap().get_tcp_data(), &[0x40]);
+
+        // Discovery
+        let mut discovery = PppoeDiscovery::new(PPPOE_CODE_PADI);
+        discovery.tags.push(PppoeTag { tag_type: PPPOE_TAG_SERVICE_NAME, value: vec![] });
+        discovery.tags.push(PppoeTag { tag_type: PPPOE_TAG_HOST_UNIQ, value: vec![0xDE, 0xAD, 0xBE, 0xEF] });
+
+        let bytes = discovery.get_bytes();
+
+        assert_eq!(bytes, vec![
+            0x11, 0x09, 0x00, 0x00, 0x00, 0x0C,
+            0x01, 0x01, 0x00, 0x00,
+            0x01, 0x03, 0x00, 0x04, 0xDE, 0xAD, 0xBE, 0xEF,
+        ]);
+
+        let parsed = PppoeDiscovery::parse(&bytes).unwrap();
+
+        assert_eq!(parsed, discovery);
+        assert_eq!(parsed.get_tag(PPPOE_TAG_HOST_UNIQ), Some(&[0xDE, 0xAD, 0xBE, 0xEF][..]));
+        assert_eq!(parsed.get_tag(PPPOE_TAG_AC_NAME), None);
+
+        let mut bytes = bytes;
+        bytes[13] = 0x05;
+
+        assert_eq!(
+            PppoeDiscovery::parse(&bytes).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Pppoe, offset: 12, expected: 5, actual: 4 }),
+        );
+
+        // Discovery frames, without the session header of the builder
+        packet_builder.pppoe_header = Some(PppoeHeader::new(0x1234));
+
+        let packet = packet_builder.build_pppoe_discovery(&discovery).unwrap();
+
+        assert_eq!(packet.link_layer.get_ether_type(), ETHER_TYPE_PPPOE_DISCOVERY);
+        assert_eq!(&packet.get_bytes()[packet.get_ip_offset() ..], &discovery.get_bytes()[..]);
+
+        let mut frame = packet.get_bytes().to_vec();
+        frame.resize(80, 0);
+
+        let parsed = Packet::parse_with(&frame, &ethernet).unwrap();
+
+        assert_eq!(parsed.get_pppoe_discovery(), Some(&discovery));
+        assert_eq!(parsed.tcp_header.is_none(), true);
+        assert_eq!(parsed.get_bytes(), packet.get_bytes());
+        assert_eq!(PacketView::parse_with(&frame, &ethernet).unwrap().get_pppoe_discovery().unwrap().code(), PPPOE_CODE_PADI);
+
+        frame[31] = 0x05;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &ethernet).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Pppoe, offset: 30, expected: 5, actual: 4 }),
+        );
+
+        // Session length must agree with the IP length
+        let mut frame = packet_builder.build().unwrap().get_bytes().to_vec();
+        frame[23] = 0x2C;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &ethernet).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Pppoe, offset: 22, expected: 0x2B, actual: 0x2C }),
+        );
+
+        let lenient = ParseOptions { check_total_length: false, ..ethernet };
+
+        assert_eq!(Packet::parse_with(&frame, &lenient).unwrap().get_tcp_data(), &[0x40]);
+
+        // Session length counts the PPP protocol too
+        packet_builder.bytes = vec![0; 65535 - 40];
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::PacketTooLarge { max: 65533, actual: 65535 }));
+
+        packet_builder.bytes = vec![0; 65533 - 40];
+
+        assert_eq!(Packet::parse_with(packet_builder.build().unwrap().get_bytes(), &ethernet).is_ok(), true);
+
+        discovery.tags.push(PppoeTag { tag_type: PPPOE_TAG_AC_COOKIE, value: vec![0; 65535 - 12 - 4 + 1] });
+
+        assert_eq!(packet_builder.build_pppoe_discovery(&discovery).err(), Some(BuildError::PacketTooLarge { max: 65541, actual: 65542 }));
+
+    }
+
+    #[test]
+    fn test_mpls() {
+
+        let ethernet = ParseOptions { framing: Framing::Ethernet, ..ParseOptions::default() };
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::Ethernet;
+        packet_builder.mpls_labels = vec![MplsLabel { tc: 5, ..MplsLabel::new(100) }, MplsLabel::new(200)];
+        packet_builder.bytes = vec![0x40];
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.get_bytes()[12 .. 23], &[0x88, 0x47, 0x00, 0x06, 0x4A, 0x40, 0x00, 0x0C, 0x81, 0x40, 0x45]);
+        assert_eq!(packet.get_ip_offset(), 22);
+
+        let parsed = Packet::parse_with(packet.get_bytes(), &ethernet).unwrap();
+
+        let LinkLayer::Mpls { labels, ether_type, .. } = &parsed.link_layer else { panic!() };
+
+        assert_eq!(labels, &vec![
+            MplsLabel { label: 100, tc: 5, bottom_of_stack: false, ttl: 64 },
+            MplsLabel { label: 200, tc: 0, bottom_of_stack: true, ttl: 64 },
+        ]);
+        assert_eq!(*ether_type, 0x0800);
+        assert_eq!(parsed.link_layer, packet.link_layer);
+        assert_eq!(parsed.get_tcp_data(), &[0x40]);
+
+        // Payload guessed from its first nibble, the IPv4 bytes behind it make a poor IPv6 header
+        let mut frame = packet.get_bytes().to_vec();
+        frame[22] = 0x60;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &ethernet).err(),
+            Some(ParseError::Truncated { layer: Layer::Ipv6, offset: 62, expected: 8, actual: 1 }),
+        );
+
+        // Pseudowire control word
+        frame[22] = 0x00;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &ethernet).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Mpls, offset: 22, expected: 0x0800, actual: 0x0000 }),
+        );
+
+        // Explicit null labels are decisive
+        let labels = vec![MplsLabel::new(MPLS_LABEL_IPV6_EXPLICIT_NULL)];
+
+        assert_eq!(MplsLabel::detect_ether_type(&labels, &[0x45]), Some(0x86DD));
+        assert_eq!(MplsLabel::detect_ether_type(&[MplsLabel::new(MPLS_LABEL_IPV4_EXPLICIT_NULL)], &[]), Some(0x0800));
+        assert_eq!(MplsLabel::detect_ether_type(&[MplsLabel::new(16)], &[]), None);
+
+        assert_eq!(MplsLabel::encode_stack(&labels), vec![0x00, 0x00, 0x21, 0x40]);
+        assert_eq!(MplsLabel::parse([0xFF, 0xFF, 0xFF, 0xFF]), MplsLabel { label: 0xFFFFF, tc: 7, bottom_of_stack: true, ttl: 255 });
+        assert_eq!(MplsLabel::parse([0xFF, 0xFF, 0xFF, 0xFF]).get_bytes(), [0xFF, 0xFF, 0xFF, 0xFF]);
+
+        // Bottom of stack missing
+        frame[20] = 0x80;
+
+        assert_eq!(
+            Packet::parse_with(&frame[.. 26], &ethernet).err(),
+            Some(ParseError::Truncated { layer: Layer::Mpls, offset: 14, expected: 16, actual: 12 }),
+        );
+
+    }
+
+    #[test]
+    fn test_ipv6() {
+
+        let source_address: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let destination_address: Ipv6Addr = "2001:db8::2".parse().unwrap();
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::NoPacketInfo;
+        packet_builder.ipv6_header_builder = Some(
+            Ipv6HeaderBuilder {
+                source_address,
+                destination_address,
+                traffic_class: 0xB8,
+                flow_label: 0x12345,
+                ..Ipv6HeaderBuilder::new()
+            }
+        );
+        packet_builder.tcp_header_builder.source_port = 46046;
+        packet_builder.tcp_header_builder.destination_port = 443;
+        packet_builder.bytes = vec![0x40, 0x41, 0x42];
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.get_bytes()[.. 8], &[0x6B, 0x81, 0x23, 0x45, 0x00, 0x17, 0x06, 0x40]);
+        assert_eq!(packet.get_ip_header().is_none(), true);
+
+        // Parsed back with the TCP checksum verified against the IPv6 pseudo header
+        let parsed = Packet::parse_with(packet.get_bytes(), &ParseOptions { framing: Framing::Auto, ..ParseOptions::default() }).unwrap();
+        let ipv6_header = parsed.get_ipv6_header().unwrap();
+
+        assert_eq!(parsed.link_layer, LinkLayer::None);
+        assert_eq!(ipv6_header.version, 6);
+        assert_eq!(ipv6_header.traffic_class, 0xB8);
+        assert_eq!(ipv6_header.flow_label, 0x12345);
+        assert_eq!(ipv6_header.payload_length, 23);
+        assert_eq!(ipv6_header.next_header, 6);
+        assert_eq!(ipv6_header.hop_limit, 64);
+        assert_eq!(ipv6_header.source_address, source_address);
+        assert_eq!(ipv6_header.destination_address, destination_address);
+        assert_eq!(parsed.tcp_header.as_ref().unwrap().destination_port, 443);
+        assert_eq!(parsed.get_tcp_data(), &[0x40, 0x41, 0x42]);
+
+        let view = PacketView::parse_with(packet.get_bytes(), &ParseOptions { framing: Framing::NoPacketInfo, ..ParseOptions::default() }).unwrap();
+
+        assert_eq!(view.get_ipv6_header().unwrap().flow_label(), 0x12345);
+        assert_eq!(view.get_tcp_data(), &[0x40, 0x41, 0x42]);
+
+        let mut bytes = packet.get_bytes().to_vec();
+        bytes[39] ^= 0x01;
+
+        assert_eq!(
+            Packet::parse_with(&bytes, &ParseOptions { framing: Framing::NoPacketInfo, ..ParseOptions::default() }).err().map(|error| error.layer()),
+            Some(Layer::Tcp),
+        );
+
+        bytes[6] = 17;
+
+        assert_eq!(
+            Ipv6Header::parse(&bytes).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Ipv6, offset: 6, expected: 6, actual: 17 }),
+        );
+
+        assert_eq!(
+            Ipv6Header::parse(&bytes[.. 39]).err(),
+            Some(ParseError::Truncated { layer: Layer::Ipv6, offset: 0, expected: 40, actual: 39 }),
+        );
+
+        // Link layers carry the IPv6 EtherType
+        packet_builder.framing = Framing::PacketInfo;
+        assert_eq!(&packet_builder.build().unwrap().get_bytes()[.. 4], &[0x00, 0x00, 0x86, 0xDD]);
+
+        packet_builder.framing = Framing::Ethernet;
+        packet_builder.pppoe_header = Some(PppoeHeader::new(0x0001));
+
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(&packet.get_bytes()[18 .. 22], &[0x00, 0x41, 0x00, 0x57]);
+        assert_eq!(Packet::parse_with(packet.get_bytes(), &ParseOptions { framing: Framing::Ethernet, ..ParseOptions::default() }).is_ok(), true);
+
+        // GSO super-packet cut into segments with IPv6 checksums
+        packet_builder.pppoe_header = None;
+        packet_builder.framing = Framing::VirtioNet;
+        packet_builder.virtio_net_header = VirtioNetHeader {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type: VIRTIO_NET_HDR_GSO_TCPV6,
+            gso_size: 2,
+            ..VirtioNetHeader::new()
+        };
+
+        let packet = packet_builder.build().unwrap();
+        let virtio_net_header = packet.get_virtio_net_header().unwrap();
+
+        assert_eq!(virtio_net_header.header_length, 60);
+        assert_eq!(virtio_net_header.checksum_start, 40);
+
+        let segments = packet.segment().unwrap();
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].get_tcp_data(), &[0x40, 0x41]);
+        assert_eq!(segments[1].get_tcp_data(), &[0x42]);
+        assert_eq!(segments[1].get_ipv6_header().unwrap().payload_length, 21);
+
+        for segment in segments.iter() {
+            assert_eq!(Packet::parse_with(segment.get_bytes(), &ParseOptions { framing: Framing::VirtioNet, ..ParseOptions::default() }).is_ok(), true);
+        }
+
+    }
+
+    #[test]
+    fn test_ipv6_extensions() {
+
+        let raw = ParseOptions { framing: Framing::NoPacketInfo, ..ParseOptions::default() };
+
+        let destination_address: Ipv6Addr = "2001:db8::2".parse().unwrap();
+        let final_destination: Ipv6Addr = "2001:db8::99".parse().unwrap();
+
+        let extensions = vec![
+            Ipv6Extension::HopByHop { options: vec![0x05, 0x02, 0x00, 0x00, 0x01, 0x00] },     // Router alert, PadN
+            Ipv6Extension::SegmentRouting {
+                segments_left: 1,
+                flags: 0,
+                tag: 7,
+                segments: vec![final_destination, destination_address],
+                tlvs: Vec::new(),
+            },
+            Ipv6Extension::Fragment { fragment_offset: 0, more_fragments: false, identification: 0xDEADBEEF },
+            Ipv6Extension::Authentication { spi: 0x100, sequence_number: 1, icv: vec![0xAA; 12] },
+            Ipv6Extension::DestinationOptions { options: Vec::new() },
+        ];
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.framing = Framing::NoPacketInfo;
+        packet_builder.ipv6_header_builder = Some(
+            Ipv6HeaderBuilder {
+                source_address: "2001:db8::1".parse().unwrap(),
+                destination_address,
+                extensions: extensions.clone(),
+                ..Ipv6HeaderBuilder::new()
+            }
+        );
+        packet_builder.bytes = vec![0x40];
+
+        let packet = packet_builder.build().unwrap();
+        let bytes = packet.get_bytes();
+
+        // Next headers linked up: HBH, routing, fragment, AH, destination options, TCP
+        assert_eq!(bytes[6], 0);
+        assert_eq!(bytes[40], IPV6_NEXT_HEADER_ROUTING);
+        assert_eq!(bytes[48], 44);
+        assert_eq!(bytes[88], 51);
+        assert_eq!(bytes[96], 60);
+        assert_eq!(bytes[120], 6);
+        assert_eq!(&bytes[122 .. 128], &[0x01, 0x04, 0x00, 0x00, 0x00, 0x00]);
+
+        // TCP found behind the chain, checksum against the final destination
+        let parsed = Packet::parse_with(bytes, &raw).unwrap();
+        let ipv6_header = parsed.get_ipv6_header().unwrap();
+
+        assert_eq!(ipv6_header.get_length(), 128);
+        assert_eq!(ipv6_header.get_data_length(), 21);
+        assert_eq!(ipv6_header.get_final_destination_address(), final_destination);
+        assert_eq!(&ipv6_header.extensions[.. 4], &extensions[.. 4]);
+        assert_eq!(ipv6_header.extensions[4], Ipv6Extension::DestinationOptions { options: vec![0x01, 0x04, 0x00, 0x00, 0x00, 0x00] });
+        assert_eq!(parsed.get_tcp_data(), &[0x40]);
+
+        let view = PacketView::parse_with(bytes, &raw).unwrap();
+
+        assert_eq!(view.get_ipv6_header().unwrap().get_extensions().count(), 5);
+        assert_eq!(view.get_ipv6_header().unwrap().get_final_destination_address(), final_destination);
+        assert_eq!(view.get_ipv6_header().unwrap().is_fragment(), false);
+        assert_eq!(view.get_tcp_data(), &[0x40]);
+
+        // First fragment, TCP is left to reassembly
+        let mut fragment_builder = PacketBuilder::new();
+        fragment_builder.framing = Framing::NoPacketInfo;
+        fragment_builder.ipv6_header_builder = Some(
+            Ipv6HeaderBuilder {
+                extensions: vec![Ipv6Extension::Fragment { fragment_offset: 0, more_fragments: true, identification: 1 }],
+                ..Ipv6HeaderBuilder::new()
+            }
+        );
+
+        let fragment = fragment_builder.build().unwrap();
+        let parsed = Packet::parse_with(fragment.get_bytes(), &raw).unwrap();
+
+        assert_eq!(parsed.get_ipv6_header().unwrap().is_fragment(), true);
+        assert_eq!(parsed.tcp_header.is_none(), true);
+        assert_eq!(PacketView::parse_with(fragment.get_bytes(), &raw).unwrap().get_ipv6_header().unwrap().is_fragment(), true);
+        assert_eq!(parsed.get_bytes(), fragment.get_bytes());
+        assert_eq!(Packet::parse_with(bytes, &raw).unwrap().get_ipv6_header().unwrap().is_fragment(), false);
+
+        // Later fragment, what follows the fragment header is not decoded
+        fragment_builder.ipv6_header_builder.as_mut().unwrap().extensions = vec![
+            Ipv6Extension::Fragment { fragment_offset: 3, more_fragments: false, identification: 1 },
+            Ipv6Extension::DestinationOptions { options: Vec::new() },
+        ];
+
+        let fragment = fragment_builder.build().unwrap();
+        let view = PacketView::parse_with(fragment.get_bytes(), &raw).unwrap();
+
+        assert_eq!(view.get_ipv6_header().unwrap().is_fragment(), true);
+        assert_eq!(view.get_ipv6_header().unwrap().get_extensions().count(), 1);
+        assert_eq!(view.get_ipv6_header().unwrap().get_length(), 48);
+        assert_eq!(view.tcp_header.is_none(), true);
+        assert_eq!(PacketView::parse_with(fragment.get_bytes(), &ParseOptions { framing: Framing::NoPacketInfo, ..ParseOptions::new(ValidationPolicy::None) }).unwrap().tcp_header.is_none(), true);
+
+        // Encrypted payload hides the upper layer
+        let mut esp_builder = PacketBuilder::new();
+        esp_builder.framing = Framing::NoPacketInfo;
+        esp_builder.ipv6_header_builder = Some(
+            Ipv6HeaderBuilder {
+                extensions: vec![
+                    Ipv6Extension::HopByHop { options: Vec::new() },
+                    Ipv6Extension::EncapsulatingSecurityPayload { spi: 0x200, sequence_number: 5 },
+                ],
+                ..Ipv6HeaderBuilder::new()
+            }
+        );
+
+        let esp = esp_builder.build().unwrap();
+
+        assert_eq!(
+            Packet::parse_with(esp.get_bytes(), &raw).err(),
+            Some(ParseError::UnsupportedProtocol { layer: Layer::Ipv6, offset: 40, expected: 6, actual: IPV6_NEXT_HEADER_ESP as u32 }),
+        );
+        assert_eq!(
+            esp.get_ipv6_header().unwrap().get_extensions().nth(1),
+            Some(Ok(Ipv6Extension::EncapsulatingSecurityPayload { spi: 0x200, sequence_number: 5 })),
+        );
+
+        // Header extension length running past the buffer
+        let mut frame = bytes.to_vec();
+        frame[41] = 0xFF;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &raw).err(),
+            Some(ParseError::Truncated { layer: Layer::Ipv6, offset: 40, expected: 2048, actual: 109 }),
+        );
+
+        // Last entry naming more segments than the routing header holds
+        let mut frame = bytes.to_vec();
+        frame[52] = 5;
+
+        assert_eq!(
+            Packet::parse_with(&frame, &raw).err(),
+            Some(ParseError::InvalidLength { layer: Layer::Ipv6, offset: 52, expected: 104, actual: 40 }),
+        );
+
+        // Every cut and every corrupted next header comes back as an error
+        for length in 0 .. bytes.len() {
+            assert_eq!(Packet::parse_with(&bytes[.. length], &raw).is_err(), true);
+        }
+
+        for next_header in 0 ..= 255 {
+            let mut frame = bytes.to_vec();
+            frame[48] = next_header;
+            let _ = Packet::parse_with(&frame, &raw);
+        }
+
+    }
+
+    #[test]
+    fn test_pseudo_header() {
+
+        // Same checksum as the IPv4 only helper
+        let pseudo_header = PseudoHeader::Ipv4 { source_address: 0xC0A80032, destination_address: 0xC0A80002 };
+
+        assert_eq!(pseudo_header.calculate_checksum(IP_PROTOCOL_TCP, &REQUEST[24 ..]), 0xCCC8);
+        assert_eq!(pseudo_header.calculate_partial_checksum(IP_PROTOCOL_TCP, 41), TCPHeader::calculate_pseudo_header_sum(0xC0A80032, 0xC0A80002, 41));
+
+        let request = Packet::parse(&REQUEST).unwrap();
+
+        assert_eq!(request.network_header.get_pseudo_header(), Some(pseudo_header));
+
+        // UDP over IPv6, checksum field skipped
+        let pseudo_header = PseudoHeader::Ipv6 { source_address: "2001:db8::1".parse().unwrap(), destination_address: "2001:db8::2".parse().unwrap() };
+        let udp = [0x04, 0xD2, 0x00, 0x35, 0x00, 0x0C, 0x12, 0x34, 0x61, 0x62, 0x63, 0x64];
+
+        assert_eq!(pseudo_header.calculate_checksum(IP_PROTOCOL_UDP, &udp), 0xDA93);
+
+        // 32 bit length counts for IPv6
+        assert_eq!(pseudo_header.get_sum(IP_PROTOCOL_TCP, 0x10000), pseudo_header.get_sum(IP_PROTOCOL_TCP, 0) + 1);
+
+        // Builders and parsers agree on the IPv6 pseudo header
+        let ipv6_header_builder = Ipv6HeaderBuilder {
+            source_address: "2001:db8::1".parse().unwrap(),
+            destination_address: "2001:db8::2".parse().unwrap(),
+            ..Ipv6HeaderBuilder::new()
+        };
+
+        let tcp_header = TCPHeaderBuilder::new().build_with(&ipv6_header_builder.get_pseudo_header(), &[0x40]).unwrap();
+        let ipv6_header = ipv6_header_builder.build(&tcp_header, 1).unwrap();
+
+        assert_eq!(ipv6_header.get_pseudo_header(), pseudo_header);
+
+        let mut segment = tcp_header.get_bytes().to_vec();
+        segment.push(0x40);
+
+        assert_eq!(pseudo_header.calculate_checksum(IP_PROTOCOL_TCP, &segment), tcp_header.checksum);
+        assert_eq!(TCPHeader::parse_ipv6(&ipv6_header, &segment, &ParseOptions::default()).is_ok(), true);
+
+        segment[20] = 0x41;
+
+        assert_eq!(TCPHeader::parse_ipv6(&ipv6_header, &segment, &ParseOptions::default()).err().map(|error| error.layer()), Some(Layer::Tcp));
+
+    }
+
+    #[test]
+    fn test_fragmentation() {
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.source_address = 0xC0A80032;
+        packet_builder.ip_header_builder.destination_address = 0xC0A80002;
+        packet_builder.ip_header_builder.identification = Identification::Fixed(0x1234);
+        packet_builder.ip_header_builder.options = vec![
+            Ipv4Option::RecordRoute { pointer: 4, addresses: vec![0] },                 // Not copied
+            Ipv4Option::LooseSourceRoute { pointer: 4, addresses: vec![0x0A000001] },   // Copied
+        ];
+        packet_builder.bytes = (0 .. 100).collect();
+        packet_builder.mtu = Some(76);
+
+        assert_eq!(packet_builder.build().err(), Some(BuildError::ExceedsMtu { mtu: 76, actual: 156 }));
+
+        let fragments = packet_builder.build_fragments().unwrap();
+
+        packet_builder.mtu = None;
+        let packet = packet_builder.build().unwrap();
+        packet_builder.mtu = Some(76);
+
+        // 40 data bytes after the 36 byte first header, 48 after the 28 byte copies, 32 left
+        assert_eq!(fragments.len(), 3);
+
+        let headers: Vec<&IPHeader> = fragments.iter().map(|fragment| fragment.get_ip_header().unwrap()).collect();
+
+        assert_eq!(headers.iter().map(|header| header.total_length).collect::<Vec<u16>>(), vec![76, 76, 60]);
+        assert_eq!(headers.iter().map(|header| header.header_length).collect::<Vec<u8>>(), vec![36, 28, 28]);
+        assert_eq!(headers.iter().map(|header| header.fragment_offset).collect::<Vec<u16>>(), vec![0, 5, 11]);
+        assert_eq!(headers.iter().map(|header| header.more_fragments).collect::<Vec<bool>>(), vec![true, true, false]);
+        assert_eq!(headers.iter().all(|header| header.identification == 0x1234), true);
+        assert_eq!(fragments.iter().all(|fragment| fragment.tcp_header.is_none()), true);
+
+        assert_eq!(
+            headers[1].get_options().map(Result::unwrap).collect::<Vec<Ipv4Option>>(),
+            vec![Ipv4Option::LooseSourceRoute { pointer: 4, addresses: vec![0x0A000001] }, Ipv4Option::EndOfList],
+        );
+
+        // Header checksums hold and the data comes back in order
+        let mut data = Vec::new();
+
+        for fragment in fragments.iter() {
+            let ip_header = fragment.get_ip_header().unwrap();
+            assert_eq!(IPHeader::calculate_checksum(ip_header.get_bytes()), ip_header.header_checksum);
+            assert_eq!(&fragment.get_bytes()[.. 4], &[0x00, 0x00, 0x08, 0x00]);
+            data.extend_from_slice(&fragment.get_bytes()[4 + ip_header.header_length as usize ..]);
+        }
+
+        assert_eq!(&data[..], &packet.get_bytes()[40 ..]);
+
+        // Fragments of fragments keep their place in the original datagram
+        let refragmented = fragments[1].fragment(52).unwrap();
+
+        assert_eq!(refragmented.iter().map(|fragment| fragment.get_ip_header().unwrap().fragment_offset).collect::<Vec<u16>>(), vec![5, 8]);
+        assert_eq!(refragmented[1].get_ip_header().unwrap().more_fragments, true);
+
+        // Datagrams that fit are left alone
+        assert_eq!(packet.fragment(156).unwrap()[0].get_bytes(), packet.get_bytes());
+        assert_eq!(packet.fragment(156).unwrap()[0].tcp_header.is_some(), true);
+
+        // Don't fragment
+        packet_builder.ip_header_builder.dont_fragment = true;
+
+        assert_eq!(packet_builder.build_fragments().err(), Some(BuildError::FragmentationNeeded { mtu: 76, actual: 156 }));
+
+        // No room for 8 data bytes
+        packet_builder.ip_header_builder.dont_fragment = false;
+        packet_builder.mtu = Some(40);
+
+        assert_eq!(packet_builder.build_fragments().err(), Some(BuildError::ExceedsMtu { mtu: 40, actual: 44 }));
+
+        // Checksum left to the device is finished before the header is swapped for a plain one
+        let strict = ParseOptions { framing: Framing::VirtioNet, ..ParseOptions::new(ValidationPolicy::Strict) };
+
+        let mut virtio_net_builder = PacketBuilder::new();
+        virtio_net_builder.framing = Framing::VirtioNet;
+        virtio_net_builder.virtio_net_header.flags = VIRTIO_NET_HDR_F_NEEDS_CSUM;
+        virtio_net_builder.bytes = (0 .. 100).collect();
+
+        let packet = virtio_net_builder.build().unwrap();
+        let fragments = packet.fragment(60).unwrap();
+        let mut reassembler = Reassembler::new();
+        let now = Instant::now();
+
+        assert_eq!(fragments.len(), 3);
+        assert_eq!(fragments.iter().all(|fragment| !fragment.get_virtio_net_header().unwrap().needs_checksum()), true);
+        assert_eq!(reassembler.insert(fragments[0].clone(), now).unwrap().is_none(), true);
+        assert_eq!(reassembler.insert(fragments[1].clone(), now).unwrap().is_none(), true);
+
+        let reassembled = reassembler.insert(fragments[2].clone(), now).unwrap().unwrap();
+
+        assert_eq!(Packet::parse_with(reassembled.get_bytes(), &strict).is_ok(), true);
+
+        // Super-packets are segmented, not fragmented
+        virtio_net_builder.virtio_net_header.gso_type = VIRTIO_NET_HDR_GSO_TCPV4;
+        virtio_net_builder.virtio_net_header.gso_size = 40;
+
+        let packet = virtio_net_builder.build().unwrap();
+
+        assert_eq!(packet.fragment(60).err(), Some(BuildError::SegmentationNeeded { mtu: 60, actual: 140 }));
+        assert_eq!(packet.segment().unwrap().iter().all(|segment| segment.fragment(60).is_ok()), true);
+
+    }
+
+    #[test]
+    fn test_reassembly() {
+
+        let mut packet_builder = PacketBuilder::new();
+        packet_builder.ip_header_builder.source_address = 0xC0A80032;
+        packet_builder.ip_header_builder.destination_address = 0xC0A80002;
+        packet_builder.ip_header_builder.identification = Identification::Fixed(0x1234);
+        packet_builder.ip_header_builder.options = vec![
+            Ipv4Option::LooseSourceRoute { pointer: 4, addresses: vec![0x0A000001] },
+        ];
+        packet_builder.bytes = (0 .. 100).collect();
+        packet_builder.mtu = Some(76);
+
+        // 48, 48 and 24 data bytes
+        let fragments = packet_builder.build_fragments().unwrap();
+
+        packet_builder.mtu = None;
+        let packet = packet_builder.build().unwrap();
+
+        assert_eq!(fragments.len(), 3);
+
+        // Fragments are not decoded past IP
+        let parsed = Packet::parse(fragments[1].get_bytes()).unwrap();
+
+        assert_eq!(parsed.tcp_header.is_none(), true);
+        assert_eq!(parsed.get_ip_header().unwrap().is_fragment(), true);
+
+        // Out of order, with a retransmission
+        let now = Instant::now();
+        let mut reassembler = Reassembler::new();
+
+        assert_eq!(reassembler.insert(fragments[2].clone(), now).unwrap().is_none(), true);
+        assert_eq!(reassembler.insert(fragments[0].clone(), now).unwrap().is_none(), true);
+        assert_eq!(reassembler.insert(fragments[0].clone(), now).unwrap().is_none(), true);
+        assert_eq!(reassembler.get_pending(), 1);
+        assert_eq!(reassembler.get_memory(), 120);
+
+        let reassembled = reassembler.insert(fragments[1].clone(), now).unwrap().unwrap();
+
+        assert_eq!(reassembled.get_bytes(), packet.get_bytes());
+        assert_eq!(reassembled.tcp_header.is_some(), true);
+        assert_eq!(reassembled.get_tcp_data(), &packet_builder.bytes[..]);
+        assert_eq!(reassembler.get_pending(), 0);
+        assert_eq!(reassembler.get_memory(), 0);
+
+        // Whole datagrams pass through
+        assert_eq!(reassembler.insert(packet.clone(), now).unwrap().unwrap().get_bytes(), packet.get_bytes());
+
+        // Same range, different bytes
+        let mut overlapping = fragments[1].clone();
+        *overlapping.bytes.last_mut().unwrap() ^= 0xFF;
+
+        reassembler.insert(fragments[0].clone(), now).unwrap();
+        reassembler.insert(fragments[1].clone(), now).unwrap();
+
+        assert_eq!(reassembler.insert(overlapping.clone(), now).err(), Some(ReassemblyError::Overlap { offset: 48, length: 48 }));
+        assert_eq!(reassembler.get_pending(), 0);
+        assert_eq!(reassembler.get_memory(), 0);
+
+        // Overlap running past the data held
+        let mut longer = fragments[1].clone();
+        longer.bytes.extend_from_slice(&[0; 8]);
+
+        if let NetworkHeader::Ipv4(ip_header) = &mut longer.network_header {
+            ip_header.total_length += 8;
+        }
+
+        if let NetworkHeader::Ipv4(ip_header) = &mut longer.network_header {
+            ip_header.fragment_offset = 5;
+        }
+
+        reassembler.insert(fragments[0].clone(), now).unwrap();
+
+        assert_eq!(reassembler.insert(longer, now).err(), Some(ReassemblyError::Overlap { offset: 40, length: 56 }));
+        assert_eq!(reassembler.get_pending(), 0);
+        assert_eq!(reassembler.get_memory(), 0);
+
+        reassembler.overlap_policy = OverlapPolicy::KeepFirst;
+
+        reassembler.insert(fragments[0].clone(), now).unwrap();
+        reassembler.insert(fragments[1].clone(), now).unwrap();
+        reassembler.insert(overlapping.clone(), now).unwrap();
+
+        assert_eq!(reassembler.insert(fragments[2].clone(), now).unwrap().unwrap().get_bytes(), packet.get_bytes());
+
+        reassembler.overlap_policy = OverlapPolicy::KeepLast;
+
+        reassembler.insert(fragments[0].clone(), now).unwrap();
+        reassembler.insert(fragments[1].clone(), now).unwrap();
+        reassembler.insert(overlapping.clone(), now).unwrap();
+
+        // Newer bytes win and break the TCP checksum
+        let result = reassembler.insert(fragments[2].clone(), now);
+
+        assert_eq!(matches!(result, Err(ReassemblyError::Parse(ParseError::ChecksumMismatch { layer: Layer::Tcp, .. }))), true);
+
+        // Timeout counts from the first fragment
+        reassembler.insert(fragments[0].clone(), now).unwrap();
+
+        assert_eq!(reassembler.expire(now + Duration::from_secs(29)), 0);
+        assert_eq!(reassembler.expire(now + Duration::from_secs(30)), 1);
+        assert_eq!(reassembler.get_memory(), 0);
+
+        // Only the last fragment may end off the 8 byte grid
+        let mut misaligned = fragments[0].clone();
+        misaligned.bytes.pop();
+
+        assert_eq!(reassembler.insert(misaligned, now).err(), Some(ReassemblyError::InvalidLength { expected: 48, actual: 47 }));
+
+        // Oldest datagrams are evicted first, then the fragment is refused
+        let mut other = fragments[0].clone();
+
+        if let NetworkHeader::Ipv4(ip_header) = &mut other.network_header {
+            ip_header.identification = 0x4321;
+        }
+
+        reassembler.max_memory = 64;
+        reassembler.insert(fragments[0].clone(), now).unwrap();
+        reassembler.insert(other, now + Duration::from_secs(1)).unwrap();
+
+        assert_eq!(reassembler.get_pending(), 1);
+        assert_eq!(reassembler.get_memory(), 48);
+        assert_eq!(reassembler.insert(fragments[1].clone(), now).err(), Some(ReassemblyError::MemoryExhausted { limit: 64, actual: 96 }));
+        assert_eq!(reassembler.get_pending(), 0);
+        assert_eq!(reassembler.get_memory(), 0);
+
+        // Space in front of a far fragment counts too
+        let mut far = fragments[2].clone();
+
+        if let NetworkHeader::Ipv4(ip_header) = &mut far.network_header {
+            ip_header.fragment_offset = 1000;
+        }
+
+        reassembler.max_memory = 1024;
+
+        assert_eq!(reassembler.insert(far, now).err(), Some(ReassemblyError::MemoryExhausted { limit: 1024, actual: 8024 }));
+        assert_eq!(reassembler.get_memory(), 0);
+
+    }
+
 }
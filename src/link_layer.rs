@@ -0,0 +1,257 @@
+// Everything in front of the network header, for each device or capture format we read from
+// https://www.kernel.org/doc/Documentation/networking/tuntap.txt
+// https://www.tcpdump.org/linktypes.html
+// https://tools.ietf.org/html/rfc2516
+// https://tools.ietf.org/html/rfc3032
+
+use crate::error::{Layer, ParseError};
+use crate::headers::ethernet_header::EthernetHeader;
+use crate::headers::mpls_label::MplsLabel;
+use crate::headers::pppoe_header::{PppoeHeader, ETHER_TYPE_PPPOE_SESSION, PPP_IPV4};
+use crate::headers::sll_header::{Sll2Header, SllHeader};
+use crate::headers::tun_header::TunHeader;
+use crate::headers::virtio_net_header::VirtioNetHeader;
+use crate::headers::vlan_tag::VlanTag;
+
+// How packets are framed on the device or capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+
+    PacketInfo,                     // Default, 4 byte prefix in front of the IP header
+    NoPacketInfo,                   // IFF_NO_PI, packet starts at the IP header
+    Auto,                           // Raw if the first nibble is an IP version, packet info otherwise. Builds with packet info
+    Ethernet,                       // TAP device, Ethernet II frame, PPPoE sessions and MPLS included
+    VirtioNet,                      // IFF_VNET_HDR with IFF_NO_PI, virtio-net header in front of the IP header
+    Sll,                            // Linux cooked capture
+    Sll2,                           // Linux cooked capture v2
+
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkLayer {
+
+    None,                                           // TUN without packet info, IP follows
+    Tun(TunHeader),
+    Ethernet(EthernetHeader),
+    Pppoe(EthernetHeader, PppoeHeader),             // PPPoE session, EtherType 0x8864
+    Mpls {
+        ethernet_header: EthernetHeader,
+        labels: Vec<MplsLabel>,                     // Outermost first
+        ether_type: u16,                            // Payload as detected beneath the stack, 0 if unknown
+    },
+    VirtioNet(VirtioNetHeader),                     // IP follows
+    Sll(SllHeader),
+    Sll2(Sll2Header),
+
+}
+
+impl Framing {
+
+    // Whether a packet starting with these bytes carries packet info
+    pub fn has_packet_info(&self, bytes: &[u8]) -> bool {
+
+        match self {
+            Framing::PacketInfo => true,
+            Framing::NoPacketInfo | Framing::Ethernet | Framing::VirtioNet | Framing::Sll | Framing::Sll2 => false,
+            Framing::Auto => match bytes.first() {
+                Some(byte) => !matches!(byte >> 4, 4 | 6),
+                None => true,
+            },
+        }
+
+    }
+
+}
+
+impl LinkLayer {
+
+    pub fn parse(bytes: &[u8], framing: Framing) -> Result<LinkLayer, ParseError> {
+
+        Ok(
+            match framing {
+                Framing::Ethernet => {
+                    let ethernet_header = EthernetHeader::parse(bytes)?;
+                    let offset = ethernet_header.get_length();
+                    match ethernet_header.ether_type {
+                        ETHER_TYPE_PPPOE_SESSION => {
+                            let pppoe_header = PppoeHeader::parse(&bytes[offset ..]).map_err(|error| error.shift(offset))?;
+                            LinkLayer::Pppoe(ethernet_header, pppoe_header)
+                        },
+                        ether_type if MplsLabel::is_ether_type(ether_type) => {
+                            let labels = MplsLabel::parse_stack(&bytes[offset ..]).map_err(|error| error.shift(offset))?;
+                            let payload = &bytes[offset + labels.len() * 4 ..];
+                            let ether_type = MplsLabel::detect_ether_type(&labels, payload).unwrap_or(0);
+                            LinkLayer::Mpls { ethernet_header, labels, ether_type }
+                        },
+                        _ => LinkLayer::Ethernet(ethernet_header),
+                    }
+                },
+                Framing::VirtioNet => LinkLayer::VirtioNet(VirtioNetHeader::parse(bytes)?),
+                Framing::Sll => LinkLayer::Sll(SllHeader::parse(bytes)?),
+                Framing::Sll2 => LinkLayer::Sll2(Sll2Header::parse(bytes)?),
+                framing if framing.has_packet_info(bytes) => LinkLayer::Tun(TunHeader::parse(bytes)?),
+                _ => LinkLayer::None,
+            }
+        )
+
+    }
+
+    pub fn get_length(&self) -> usize {
+
+        match self {
+            LinkLayer::None => 0,
+            LinkLayer::Tun(_) => 4,
+            LinkLayer::Ethernet(ethernet_header) => ethernet_header.get_length(),
+            LinkLayer::Pppoe(ethernet_header, _) => ethernet_header.get_length() + 8,
+            LinkLayer::Mpls { ethernet_header, labels, .. } => ethernet_header.get_length() + labels.len() * 4,
+            LinkLayer::VirtioNet(_) => 10,
+            LinkLayer::Sll(_) => 16,
+            LinkLayer::Sll2(_) => 20,
+        }
+
+    }
+
+    pub fn get_layer(&self) -> Layer {
+
+        match self {
+            LinkLayer::None | LinkLayer::Tun(_) => Layer::Tun,
+            LinkLayer::Ethernet(_) => Layer::Ethernet,
+            LinkLayer::Pppoe(_, _) => Layer::Pppoe,
+            LinkLayer::Mpls { .. } => Layer::Mpls,
+            LinkLayer::VirtioNet(_) => Layer::VirtioNet,
+            LinkLayer::Sll(_) | LinkLayer::Sll2(_) => Layer::Sll,
+        }
+
+    }
+
+    // Protocol of the payload, implied IPv4 if the link layer has no such field.
+    // PPP protocols without an EtherType and undetected MPLS payloads give 0
+    pub fn get_ether_type(&self) -> u16 {
+
+        match self {
+            LinkLayer::None | LinkLayer::VirtioNet(_) => 0x0800,
+            LinkLayer::Tun(tun_header) => tun_header.proto,
+            LinkLayer::Ethernet(ethernet_header) => ethernet_header.ether_type,
+            LinkLayer::Pppoe(_, pppoe_header) => pppoe_header.get_ether_type().unwrap_or(0),
+            LinkLayer::Mpls { ether_type, .. } => *ether_type,
+            LinkLayer::Sll(sll_header) => sll_header.protocol,
+            LinkLayer::Sll2(sll2_header) => sll2_header.protocol,
+        }
+
+    }
+
+    // Like get_ether_type, but link layers without a protocol field look at the IP version of the payload
+    pub fn detect_ether_type(&self, payload: &[u8]) -> u16 {
+
+        match self {
+            LinkLayer::None | LinkLayer::VirtioNet(_) => match payload.first().map(|byte| byte >> 4) {
+                Some(6) => 0x86DD,
+                _ => 0x0800,
+            },
+            link_layer => link_layer.get_ether_type(),
+        }
+
+    }
+
+    // Where the protocol field sits, for errors. MPLS has none, its payload is pointed at instead
+    pub fn get_ether_type_offset(&self) -> usize {
+
+        match self {
+            LinkLayer::None | LinkLayer::VirtioNet(_) | LinkLayer::Sll2(_) => 0,
+            LinkLayer::Tun(_) => 2,
+            LinkLayer::Ethernet(ethernet_header) => ethernet_header.get_length() - 2,
+            LinkLayer::Pppoe(ethernet_header, _) => ethernet_header.get_length() + 6,
+            LinkLayer::Mpls { .. } => self.get_length(),
+            LinkLayer::Sll(_) => 14,
+        }
+
+    }
+
+    // Error for a payload that is neither IPv4 nor IPv6, in terms of the link layer's own protocol field
+    pub fn get_unsupported_protocol_error(&self) -> ParseError {
+
+        let (expected, actual) = match self {
+            LinkLayer::Pppoe(_, pppoe_header) => (PPP_IPV4, pppoe_header.protocol),
+            link_layer => (0x0800, link_layer.get_ether_type()),
+        };
+
+        ParseError::UnsupportedProtocol {
+            layer: self.get_layer(),
+            offset: self.get_ether_type_offset(),
+            expected: expected as u32,
+            actual: actual as u32,
+        }
+
+    }
+
+    // Ignored by link layers without a protocol field
+    pub fn set_ether_type(&mut self, ether_type: u16) {
+
+        match self {
+            LinkLayer::None | LinkLayer::VirtioNet(_) => (),
+            LinkLayer::Tun(tun_header) => tun_header.proto = ether_type,
+            LinkLayer::Ethernet(ethernet_header) => ethernet_header.ether_type = ether_type,
+            LinkLayer::Pppoe(_, pppoe_header) => pppoe_header.set_ether_type(ether_type),
+            LinkLayer::Mpls { ether_type: payload, .. } => *payload = ether_type,
+            LinkLayer::Sll(sll_header) => sll_header.protocol = ether_type,
+            LinkLayer::Sll2(sll2_header) => sll2_header.protocol = ether_type,
+        }
+
+    }
+
+    // Ignored by link layers without a length field
+    pub fn set_payload_length(&mut self, length: usize) {
+
+        if let LinkLayer::Pppoe(_, pppoe_header) = self {
+            pppoe_header.set_payload_length(length);
+        }
+
+    }
+
+    pub fn get_vlan_tags(&self) -> &[VlanTag] {
+
+        match self {
+            LinkLayer::Ethernet(ethernet_header) | LinkLayer::Pppoe(ethernet_header, _) | LinkLayer::Mpls { ethernet_header, .. } => {
+                &ethernet_header.vlan_tags
+            },
+            _ => &[],
+        }
+
+    }
+
+    // Framing that parses this link layer back
+    pub fn get_framing(&self) -> Framing {
+
+        match self {
+            LinkLayer::None => Framing::NoPacketInfo,
+            LinkLayer::Tun(_) => Framing::PacketInfo,
+            LinkLayer::Ethernet(_) | LinkLayer::Pppoe(_, _) | LinkLayer::Mpls { .. } => Framing::Ethernet,
+            LinkLayer::VirtioNet(_) => Framing::VirtioNet,
+            LinkLayer::Sll(_) => Framing::Sll,
+            LinkLayer::Sll2(_) => Framing::Sll2,
+        }
+
+    }
+
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+
+        match self {
+            LinkLayer::None => (),
+            LinkLayer::Tun(tun_header) => bytes.extend_from_slice(&tun_header.get_bytes()),
+            LinkLayer::Ethernet(ethernet_header) => ethernet_header.write(bytes),
+            LinkLayer::Pppoe(ethernet_header, pppoe_header) => {
+                ethernet_header.write(bytes);
+                bytes.extend_from_slice(&pppoe_header.get_bytes());
+            },
+            LinkLayer::Mpls { ethernet_header, labels, .. } => {
+                ethernet_header.write(bytes);
+                bytes.extend_from_slice(&MplsLabel::encode_stack(labels));
+            },
+            LinkLayer::VirtioNet(virtio_net_header) => bytes.extend_from_slice(&virtio_net_header.get_bytes()),
+            LinkLayer::Sll(sll_header) => bytes.extend_from_slice(&sll_header.get_bytes()),
+            LinkLayer::Sll2(sll2_header) => bytes.extend_from_slice(&sll2_header.get_bytes()),
+        }
+
+    }
+
+}
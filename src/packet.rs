@@ -1,72 +1,651 @@
 // https://github.com/torvalds/linux/blob/master/Documentation/networking/tuntap.txt
 
-use crate::headers::ip_header::{IPHeader, IPHeaderBuilder};
-use crate::headers::tcp_header::{TCPHeader, TCPHeaderBuilder};
+use crate::error::{BuildError, Layer, ParseError};
+use crate::headers::arp_packet::ArpPacket;
+use crate::headers::ethernet_header::{EthernetHeader, EthernetHeaderBuilder};
+use crate::headers::ip_header::{IPHeader, IPHeaderBuilder, Ipv4HeaderView};
+use crate::headers::ipv6_header::{Ipv6Header, Ipv6HeaderBuilder, Ipv6HeaderView};
+use crate::headers::mac_address::MacAddress;
+use crate::headers::mpls_label::{MplsLabel, ETHER_TYPE_MPLS_UNICAST};
+use crate::headers::pppoe_header::{PppoeDiscovery, PppoeDiscoveryView, PppoeHeader, ETHER_TYPE_PPPOE_DISCOVERY, ETHER_TYPE_PPPOE_SESSION};
+use crate::headers::sll_header::{Sll2Header, SllHeader};
+use crate::headers::tcp_flags::TcpFlags;
+use crate::headers::pseudo_header::{PseudoHeader, IP_PROTOCOL_TCP};
+use crate::headers::tcp_header::{TCPHeader, TCPHeaderBuilder, TcpHeaderView};
+use crate::headers::tun_header::TunHeader;
+use crate::headers::virtio_net_header::VirtioNetHeader;
+use crate::headers::vlan_tag::VlanTag;
+use crate::link_layer::{Framing, LinkLayer};
+use crate::parse_options::{ParseOptions, ValidationPolicy};
+
+// What follows the link layer
+#[derive(Debug, Clone)]
+pub enum NetworkHeader {
+
+    Ipv4(IPHeader),
+    Ipv6(Ipv6Header),
+    Arp(ArpPacket),
+    PppoeDiscovery(PppoeDiscovery),
 
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum NetworkHeaderView<'a> {
+
+    Ipv4(Ipv4HeaderView<'a>),
+    Ipv6(Ipv6HeaderView<'a>),
+    Arp(ArpPacket),
+    PppoeDiscovery(PppoeDiscoveryView<'a>),
+
+}
+
+#[derive(Clone)]
 pub struct Packet {
 
-    pub ip_header: IPHeader,
-    pub tcp_header: TCPHeader,
+    pub link_layer: LinkLayer,
+    pub network_header: NetworkHeader,
+    pub tcp_header: Option<TCPHeader>,              // None for ARP, PPPoE discovery and fragments
     pub bytes: Vec<u8>,                             // All headers and data
 
 }
 
+// Borrows the caller's buffer, headers are decoded on access
+#[derive(Debug, Clone)]
+pub struct PacketView<'a> {
+
+    pub link_layer: LinkLayer,
+    pub network_header: NetworkHeaderView<'a>,
+    pub tcp_header: Option<TcpHeaderView<'a>>,      // None for ARP, PPPoE discovery and fragments
+    bytes: &'a [u8],                                // All headers and data
+
+}
+
 pub struct PacketBuilder {
 
+    pub ethernet_header_builder: EthernetHeaderBuilder,    // Used with Ethernet framing
+    pub pppoe_header: Option<PppoeHeader>,          // Wraps Ethernet framing in a PPPoE session, length and protocol are filled in
+    pub mpls_labels: Vec<MplsLabel>,                // Label stack under Ethernet framing without PPPoE, outermost first
     pub ip_header_builder: IPHeaderBuilder,
+    pub ipv6_header_builder: Option<Ipv6HeaderBuilder>,    // Builds IPv6 instead of IPv4 when set
     pub tcp_header_builder: TCPHeaderBuilder,
     pub bytes: Vec<u8>,                             // TCP-data only
+    pub max_length: usize,                          // Largest IP datagram to build
+    pub framing: Framing,
+    pub virtio_net_header: VirtioNetHeader,         // Used with virtio-net framing, lengths and checksum offsets are filled in
+    pub mtu: Option<usize>,                         // Optional link MTU check, per segment for GSO
+
+}
+
+impl NetworkHeader {
+
+    pub fn get_length(&self) -> usize {
+
+        match self {
+            NetworkHeader::Ipv4(ip_header) => ip_header.header_length as usize,
+            NetworkHeader::Ipv6(ipv6_header) => ipv6_header.get_length(),
+            NetworkHeader::Arp(_) => 28,
+            NetworkHeader::PppoeDiscovery(discovery) => discovery.get_length(),
+        }
+
+    }
+
+    pub fn get_ether_type(&self) -> u16 {
+
+        match self {
+            NetworkHeader::Ipv4(_) => 0x0800,
+            NetworkHeader::Ipv6(_) => 0x86DD,
+            NetworkHeader::Arp(_) => 0x0806,
+            NetworkHeader::PppoeDiscovery(_) => ETHER_TYPE_PPPOE_DISCOVERY,
+        }
+
+    }
+
+    // None for ARP and PPPoE discovery, which carry no upper layer
+    pub fn get_pseudo_header(&self) -> Option<PseudoHeader> {
+
+        match self {
+            NetworkHeader::Ipv4(ip_header) => Some(ip_header.get_pseudo_header()),
+            NetworkHeader::Ipv6(ipv6_header) => Some(ipv6_header.get_pseudo_header()),
+            NetworkHeader::Arp(_) | NetworkHeader::PppoeDiscovery(_) => None,
+        }
+
+    }
+
+}
+
+impl<'a> NetworkHeaderView<'a> {
+
+    pub fn get_length(&self) -> usize {
+
+        match self {
+            NetworkHeaderView::Ipv4(ip_header) => ip_header.header_length() as usize,
+            NetworkHeaderView::Ipv6(ipv6_header) => ipv6_header.get_length(),
+            NetworkHeaderView::Arp(_) => 28,
+            NetworkHeaderView::PppoeDiscovery(discovery) => discovery.get_length(),
+        }
+
+    }
+
+    pub fn to_owned(self) -> NetworkHeader {
+
+        match self {
+            NetworkHeaderView::Ipv4(ip_header) => NetworkHeader::Ipv4(ip_header.to_owned()),
+            NetworkHeaderView::Ipv6(ipv6_header) => NetworkHeader::Ipv6(ipv6_header.to_owned()),
+            NetworkHeaderView::Arp(arp_packet) => NetworkHeader::Arp(arp_packet),
+            NetworkHeaderView::PppoeDiscovery(discovery) => NetworkHeader::PppoeDiscovery(discovery.to_owned()),
+        }
+
+    }
 
 }
 
 impl Packet {
 
-    pub fn parse(bytes: [u8; 1504], bytes_read: usize) -> Option<Packet> {
+    pub fn parse(bytes: &[u8]) -> Result<Packet, ParseError> {
+
+        Packet::parse_with(bytes, &ParseOptions::default())
+
+    }
+
+    pub fn parse_with(bytes: &[u8], options: &ParseOptions) -> Result<Packet, ParseError> {
+
+        Ok(PacketView::parse_with(bytes, options)?.to_owned())
 
-        //let eth_flags: u16 = u16::from_be_bytes([bytes[0], bytes[1]]);    // First 2 bytes are TUN/TAP flags
-        let eth_proto: u16 = u16::from_be_bytes([bytes[2], bytes[3]]);      // Second 2 bytes are TUN/TAP proto
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
 
-        if eth_proto != 0x0800 {
-            // Skip if not IPv4
-            return None;
+        &self.bytes
+
+    }
+
+    pub fn get_ip_header(&self) -> Option<&IPHeader> {
+
+        match &self.network_header {
+            NetworkHeader::Ipv4(ip_header) => Some(ip_header),
+            _ => None,
         }
 
-        // Parse internet header
-        let ip_header = match IPHeader::parse(&bytes[4 .. bytes_read]) {
-            Some(header) => header,
-            None => return None,
+    }
+
+    pub fn get_ipv6_header(&self) -> Option<&Ipv6Header> {
+
+        match &self.network_header {
+            NetworkHeader::Ipv6(ipv6_header) => Some(ipv6_header),
+            _ => None,
+        }
+
+    }
+
+    pub fn get_arp_packet(&self) -> Option<&ArpPacket> {
+
+        match &self.network_header {
+            NetworkHeader::Arp(arp_packet) => Some(arp_packet),
+            _ => None,
+        }
+
+    }
+
+    pub fn get_pppoe_discovery(&self) -> Option<&PppoeDiscovery> {
+
+        match &self.network_header {
+            NetworkHeader::PppoeDiscovery(discovery) => Some(discovery),
+            _ => None,
+        }
+
+    }
+
+    pub fn get_tcp_data(&self) -> &[u8] {
+
+        match &self.tcp_header {
+            // From TUN/TAP data length + data offset to packet end
+            Some(tcp_header) => &self.bytes[self.get_ip_offset() + self.network_header.get_length() + tcp_header.data_offset as usize ..],
+            None => &[],
+        }
+
+    }
+
+    pub fn get_ip_offset(&self) -> usize {
+
+        self.link_layer.get_length()
+
+    }
+
+    pub fn get_vlan_tags(&self) -> &[VlanTag] {
+
+        self.link_layer.get_vlan_tags()
+
+    }
+
+    // Reframes the packet for another device or capture format, keeping the payload protocol
+    pub fn set_link_layer(&mut self, mut link_layer: LinkLayer) {
+
+        link_layer.set_ether_type(self.network_header.get_ether_type());
+        link_layer.set_payload_length(self.bytes.len() - self.get_ip_offset());
+
+        let mut bytes = Vec::with_capacity(link_layer.get_length() + self.bytes.len() - self.get_ip_offset());
+        link_layer.write(&mut bytes);
+        bytes.extend_from_slice(&self.bytes[self.get_ip_offset() ..]);
+
+        self.link_layer = link_layer;
+        self.bytes = bytes;
+
+    }
+
+    pub fn get_virtio_net_header(&self) -> Option<&VirtioNetHeader> {
+
+        match &self.link_layer {
+            LinkLayer::VirtioNet(virtio_net_header) => Some(virtio_net_header),
+            _ => None,
+        }
+
+    }
+
+    // Cuts a TCP GSO super-packet into segments of at most gso_size data bytes with finished checksums.
+    // Any other packet comes back as a single segment, its checksum finished if NEEDS_CSUM was set
+    pub fn segment(&self) -> Result<Vec<Packet>, ParseError> {
+
+        let options = ParseOptions { framing: self.link_layer.get_framing(), ..ParseOptions::new(ValidationPolicy::Lenient) };
+
+        let (pseudo_header, tcp_header) = match (self.network_header.get_pseudo_header(), &self.tcp_header) {
+            (Some(pseudo_header), Some(tcp_header)) => (pseudo_header, tcp_header),
+            _ => return Ok(vec![Packet::parse_with(&self.bytes, &options)?]),
         };
 
-        // Slice bytes containing TCP header and parse it
-        let tcp_header_bytes = &bytes[4 + ip_header.header_length as usize .. 4 + ip_header.total_length as usize];
-        let tcp_header = match TCPHeader::parse(&ip_header, tcp_header_bytes) {
-            Some(header) => header,
-            None => return None,
+        let data = self.get_tcp_data();
+
+        let (link_layer, segment_size) = match &self.link_layer {
+            LinkLayer::VirtioNet(virtio_net_header) if virtio_net_header.is_tcp_gso() => {
+                (LinkLayer::VirtioNet(VirtioNetHeader::new()), virtio_net_header.gso_size as usize)
+            },
+            LinkLayer::VirtioNet(_) => (LinkLayer::VirtioNet(VirtioNetHeader::new()), data.len().max(1)),
+            link_layer => (link_layer.clone(), data.len().max(1)),
+        };
+
+        let chunks: Vec<&[u8]> = if data.is_empty() {
+            vec![data]
+        } else {
+            data.chunks(segment_size).collect()
         };
 
-        let mut packet_bytes = vec![0x00, 0x00, 0x08, 0x00];
-        packet_bytes.extend_from_slice(&bytes[4 .. bytes_read]);
+        let mut segments = Vec::with_capacity(chunks.len());
+
+        for (index, chunk) in chunks.iter().enumerate() {
+
+            let mut tcp_bytes = tcp_header.get_bytes().to_vec();
+            let tcp_length = tcp_bytes.len() + chunk.len();
+
+            let ip_bytes = match &self.network_header {
+                NetworkHeader::Ipv4(ip_header) => {
+                    let mut ip_bytes = ip_header.get_bytes().to_vec();
+
+                    let total_length = (ip_bytes.len() + tcp_length) as u16;
+                    let identification = ip_header.identification.wrapping_add(index as u16);
+
+                    ip_bytes[2 .. 4].copy_from_slice(&total_length.to_be_bytes());
+                    ip_bytes[4 .. 6].copy_from_slice(&identification.to_be_bytes());
+
+                    let checksum = IPHeader::calculate_checksum(&ip_bytes);
+                    ip_bytes[10 .. 12].copy_from_slice(&checksum.to_be_bytes());
+
+                    ip_bytes
+                },
+                NetworkHeader::Ipv6(ipv6_header) => {
+                    // No identification or header checksum, only the payload length changes
+                    let mut ip_bytes = ipv6_header.get_bytes().to_vec();
+                    let payload_length = (ip_bytes.len() - 40 + tcp_length) as u16;
+                    ip_bytes[4 .. 6].copy_from_slice(&payload_length.to_be_bytes());
+
+                    ip_bytes
+                },
+                NetworkHeader::Arp(_) | NetworkHeader::PppoeDiscovery(_) => unreachable!(),
+            };
+
+            let sequence_number = tcp_header.sequence_number.wrapping_add((index * segment_size) as u32);
+            tcp_bytes[4 .. 8].copy_from_slice(&sequence_number.to_be_bytes());
+
+            if index > 0 {
+                // Congestion window reduced is only signalled once
+                tcp_bytes[13] &= !(TcpFlags::CWR.bits() as u8);
+            }
+
+            if index < chunks.len() - 1 {
+                // Push and finish belong to the last segment
+                tcp_bytes[13] &= !((TcpFlags::PSH | TcpFlags::FIN).bits() as u8);
+            }
+
+            tcp_bytes.extend_from_slice(chunk);
+
+            let checksum = pseudo_header.calculate_checksum(IP_PROTOCOL_TCP, &tcp_bytes);
+            tcp_bytes[16 .. 18].copy_from_slice(&checksum.to_be_bytes());
+
+            let mut bytes = Vec::new();
+            link_layer.write(&mut bytes);
+            bytes.extend_from_slice(&ip_bytes);
+            bytes.extend_from_slice(&tcp_bytes);
+
+            segments.push(Packet::parse_with(&bytes, &options)?);
+
+        }
+
+        Ok(segments)
+
+    }
+
+    // Cuts an IPv4 datagram longer than mtu into fragments, each framed like this packet.
+    // Datagrams that fit come back unchanged. A checksum left to the device is finished first
+    pub fn fragment(&self, mtu: usize) -> Result<Vec<Packet>, BuildError> {
+
+        let ip_offset = self.get_ip_offset();
+        let total_length = self.bytes.len() - ip_offset;
+
+        if total_length <= mtu {
+            return Ok(vec![self.clone()]);
+        }
+
+        let ip_header = match &self.network_header {
+            NetworkHeader::Ipv4(ip_header) => ip_header,
+            // Cancel if there is no IPv4 header to fragment
+            _ => return Err(BuildError::ExceedsMtu { mtu, actual: total_length }),
+        };
+
+        if ip_header.dont_fragment {
+            // Cancel if fragmenting is forbidden
+            return Err(BuildError::FragmentationNeeded { mtu, actual: total_length });
+        }
+
+        let mut data = self.bytes[ip_offset + ip_header.header_length as usize ..].to_vec();
+
+        match &self.link_layer {
+            LinkLayer::VirtioNet(virtio_net_header) if virtio_net_header.is_tcp_gso() => {
+                // Cancel if the device was meant to cut this into segments
+                return Err(BuildError::SegmentationNeeded { mtu, actual: total_length });
+            },
+            LinkLayer::VirtioNet(virtio_net_header) if virtio_net_header.needs_checksum() && self.tcp_header.is_some() => {
+                // Fragments go out without NEEDS_CSUM, the field only holds the pseudo header sum
+                let pseudo_header = self.network_header.get_pseudo_header().expect("IPv4 has a pseudo header");
+                let checksum = pseudo_header.calculate_checksum(IP_PROTOCOL_TCP, &data);
+                data[16 .. 18].copy_from_slice(&checksum.to_be_bytes());
+            },
+            _ => (),
+        }
+
+        // Every fragment but the last carries a multiple of 8 data bytes
+        let first_length = (mtu.saturating_sub(ip_header.header_length as usize)) & !7;
+        let other_length = (mtu.saturating_sub(20 + ip_header.get_copied_options().len())) & !7;
+
+        if first_length == 0 || other_length == 0 {
+            // Cancel if not even 8 bytes of data fit
+            return Err(BuildError::ExceedsMtu { mtu, actual: ip_header.header_length as usize + 8 });
+        }
+
+        let link_layer = match &self.link_layer {
+            LinkLayer::VirtioNet(_) => LinkLayer::VirtioNet(VirtioNetHeader::new()),
+            link_layer => link_layer.clone(),
+        };
+
+        let mut fragments = Vec::new();
+        let mut offset = 0;
+
+        while offset < data.len() {
+
+            let length = if offset == 0 { first_length } else { other_length };
+            let end = data.len().min(offset + length);
+
+            let fragment_header = ip_header.get_fragment_header(offset, end - offset, end < data.len());
+
+            let mut link_layer = link_layer.clone();
+            link_layer.set_payload_length(fragment_header.total_length as usize);
+
+            let mut bytes = Vec::with_capacity(link_layer.get_length() + fragment_header.total_length as usize);
+            link_layer.write(&mut bytes);
+            bytes.extend_from_slice(fragment_header.get_bytes());
+            bytes.extend_from_slice(&data[offset .. end]);
+
+            fragments.push(
+                Packet {
+                    link_layer,
+                    network_header: NetworkHeader::Ipv4(fragment_header),
+                    tcp_header: None,
+                    bytes,
+                }
+            );
+
+            offset = end;
+
+        }
+
+        Ok(fragments)
+
+    }
+
+    // Reply frame if this is an ARP request for address, None otherwise
+    pub fn arp_reply(&self, address: u32, hardware_address: MacAddress) -> Option<Packet> {
+
+        let arp_packet = self.get_arp_packet()?.reply(address, hardware_address)?;
+
+        let link_layer = match &self.link_layer {
+            LinkLayer::Ethernet(ethernet_header) => LinkLayer::Ethernet(
+                EthernetHeader {
+                    destination_address: arp_packet.target_hardware_address,
+                    source_address: hardware_address,
+                    vlan_tags: ethernet_header.vlan_tags.clone(),
+                    ether_type: 0x0806,
+                }
+            ),
+            link_layer => link_layer.clone(),
+        };
+
+        let mut bytes = Vec::new();
+        link_layer.write(&mut bytes);
+        bytes.extend_from_slice(&arp_packet.get_bytes());
 
         Some(
             Packet {
-                ip_header,
-                tcp_header,
-                bytes: packet_bytes,
+                link_layer,
+                network_header: NetworkHeader::Arp(arp_packet),
+                tcp_header: None,
+                bytes,
             }
         )
 
     }
 
-    pub fn get_bytes(&self) -> &[u8] {
+}
+
+impl<'a> PacketView<'a> {
+
+    pub fn parse(bytes: &'a [u8]) -> Result<PacketView<'a>, ParseError> {
 
-        &self.bytes[.. 4 + self.ip_header.total_length as usize]
+        PacketView::parse_with(bytes, &ParseOptions::default())
 
     }
 
-    pub fn get_tcp_data(&self) -> &[u8] {
+    pub fn parse_with(bytes: &'a [u8], options: &ParseOptions) -> Result<PacketView<'a>, ParseError> {
+
+        let link_layer = LinkLayer::parse(bytes, options.framing)?;
+        let ip_offset = link_layer.get_length();
+        let ether_type = link_layer.detect_ether_type(&bytes[ip_offset ..]);
+
+        if ether_type == 0x0806 {
+            let arp_packet = ArpPacket::parse(&bytes[ip_offset ..]).map_err(|error| error.shift(ip_offset))?;
+            return Ok(
+                PacketView {
+                    link_layer,
+                    network_header: NetworkHeaderView::Arp(arp_packet),
+                    tcp_header: None,
+                    bytes: &bytes[.. ip_offset + 28],
+                }
+            );
+        }
 
-        // From TUN/TAP data length + data offset to TUN/TAP data length to packet end
-        &self.bytes[4 + self.ip_header.header_length as usize + self.tcp_header.data_offset as usize .. 4 + self.ip_header.total_length as usize]
+        if ether_type == ETHER_TYPE_PPPOE_DISCOVERY {
+            let discovery = PppoeDiscoveryView::parse(&bytes[ip_offset ..]).map_err(|error| error.shift(ip_offset))?;
+            return Ok(
+                PacketView {
+                    link_layer,
+                    network_header: NetworkHeaderView::PppoeDiscovery(discovery),
+                    tcp_header: None,
+                    bytes: &bytes[.. ip_offset + discovery.get_length()],
+                }
+            );
+        }
+
+        // Parse internet header
+        let (network_header, layer, header_length, mut total_length) = match ether_type {
+            0x0800 => {
+                let ip_header = Ipv4HeaderView::parse_with(&bytes[ip_offset ..], options).map_err(|error| error.shift(ip_offset))?;
+                let header_length = ip_header.header_length() as usize;
+                (NetworkHeaderView::Ipv4(ip_header), Layer::Ipv4, header_length, ip_header.total_length() as usize)
+            },
+            0x86DD => {
+                let ipv6_header = Ipv6HeaderView::parse_with(&bytes[ip_offset ..], options).map_err(|error| error.shift(ip_offset))?;
+                (NetworkHeaderView::Ipv6(ipv6_header), Layer::Ipv6, ipv6_header.get_length(), 40 + ipv6_header.payload_length() as usize)
+            },
+            // Skip if neither IPv4, IPv6 nor ARP
+            _ => return Err(link_layer.get_unsupported_protocol_error()),
+        };
+
+        if ip_offset + total_length > bytes.len() || total_length < header_length {
+            if options.check_total_length {
+                // Skip if packet was cut short
+                return Err(ParseError::Truncated { layer, offset: ip_offset, expected: total_length, actual: bytes.len() - ip_offset });
+            }
+            // Trust the buffer over a damaged length field
+            total_length = bytes.len() - ip_offset;
+        }
+
+        if let LinkLayer::Pppoe(ethernet_header, pppoe_header) = &link_layer {
+            if options.check_total_length && pppoe_header.get_payload_length() != total_length {
+                // Skip if session length disagrees with the IP length
+                return Err(
+                    ParseError::InvalidLength {
+                        layer: Layer::Pppoe,
+                        offset: ethernet_header.get_length() + 4,
+                        expected: total_length + 2,
+                        actual: pppoe_header.length as usize,
+                    }
+                );
+            }
+        }
+
+        let is_fragment = match &network_header {
+            NetworkHeaderView::Ipv4(ip_header) => ip_header.is_fragment(),
+            NetworkHeaderView::Ipv6(ipv6_header) => ipv6_header.is_fragment(),
+            NetworkHeaderView::Arp(_) | NetworkHeaderView::PppoeDiscovery(_) => false,
+        };
+
+        if is_fragment {
+            // Leave TCP to reassembly, the segment is incomplete
+            return Ok(
+                PacketView {
+                    link_layer,
+                    network_header,
+                    tcp_header: None,
+                    bytes: &bytes[.. ip_offset + total_length],
+                }
+            );
+        }
+
+        // Checksum holds only the pseudo header sum until the device finishes it
+        let needs_checksum = matches!(&link_layer, LinkLayer::VirtioNet(virtio_net_header) if virtio_net_header.needs_checksum());
+        let tcp_options = ParseOptions { verify_tcp_checksum: options.verify_tcp_checksum && !needs_checksum, ..*options };
+
+        // Slice bytes containing TCP header and parse it
+        let tcp_offset = ip_offset + header_length;
+        let tcp_bytes = &bytes[tcp_offset .. ip_offset + total_length];
+        let tcp_header = match &network_header {
+            NetworkHeaderView::Ipv6(ipv6_header) => TcpHeaderView::parse_ipv6(ipv6_header, tcp_bytes, &tcp_options),
+            NetworkHeaderView::Ipv4(ip_header) => TcpHeaderView::parse_with(ip_header, tcp_bytes, &tcp_options),
+            NetworkHeaderView::Arp(_) | NetworkHeaderView::PppoeDiscovery(_) => unreachable!(),
+        }.map_err(|error| error.shift(tcp_offset))?;
+
+        Ok(
+            PacketView {
+                link_layer,
+                network_header,
+                tcp_header: Some(tcp_header),
+                bytes: &bytes[.. ip_offset + total_length],
+            }
+        )
+
+    }
+
+    pub fn get_bytes(&self) -> &'a [u8] {
+
+        self.bytes
+
+    }
+
+    pub fn get_ip_header(&self) -> Option<Ipv4HeaderView<'a>> {
+
+        match self.network_header {
+            NetworkHeaderView::Ipv4(ip_header) => Some(ip_header),
+            _ => None,
+        }
+
+    }
+
+    pub fn get_ipv6_header(&self) -> Option<Ipv6HeaderView<'a>> {
+
+        match self.network_header {
+            NetworkHeaderView::Ipv6(ipv6_header) => Some(ipv6_header),
+            _ => None,
+        }
+
+    }
+
+    pub fn get_arp_packet(&self) -> Option<&ArpPacket> {
+
+        match &self.network_header {
+            NetworkHeaderView::Arp(arp_packet) => Some(arp_packet),
+            _ => None,
+        }
+
+    }
+
+    pub fn get_pppoe_discovery(&self) -> Option<PppoeDiscoveryView<'a>> {
+
+        match self.network_header {
+            NetworkHeaderView::PppoeDiscovery(discovery) => Some(discovery),
+            _ => None,
+        }
+
+    }
+
+    pub fn get_tcp_data(&self) -> &'a [u8] {
+
+        match self.tcp_header {
+            Some(tcp_header) => &self.bytes[self.get_ip_offset() + self.network_header.get_length() + tcp_header.data_offset() as usize ..],
+            None => &[],
+        }
+
+    }
+
+    pub fn get_ip_offset(&self) -> usize {
+
+        self.link_layer.get_length()
+
+    }
+
+    pub fn to_owned(&self) -> Packet {
+
+        Packet {
+            link_layer: self.link_layer.clone(),
+            network_header: self.network_header.to_owned(),
+            tcp_header: self.tcp_header.map(|tcp_header| tcp_header.to_owned()),
+            bytes: self.bytes.to_vec(),
+        }
+
+    }
+
+}
+
+impl Default for PacketBuilder {
+
+    fn default() -> PacketBuilder {
+
+        PacketBuilder::new()
 
     }
 
@@ -78,40 +657,207 @@ impl PacketBuilder {
 
         PacketBuilder {
             bytes: Vec::new(),
+            ethernet_header_builder: EthernetHeaderBuilder::new(),
+            pppoe_header: None,
+            mpls_labels: Vec::new(),
             ip_header_builder: IPHeaderBuilder::new(),
+            ipv6_header_builder: None,
             tcp_header_builder: TCPHeaderBuilder::new(),
+            max_length: 65535,
+            framing: Framing::PacketInfo,
+            virtio_net_header: VirtioNetHeader::new(),
+            mtu: None,
         }
 
     }
 
-    pub fn build(&self) -> Option<Packet> {
+    pub fn build(&self) -> Result<Packet, BuildError> {
 
-        let tcp_header = match self.tcp_header_builder.build(&self.ip_header_builder, &self.bytes[..]) {
-            Some(header) => header,
-            None => return None,
-        };
+        self.build_with_mtu(self.mtu)
 
-        let ip_header = match self.ip_header_builder.build(&tcp_header, self.bytes.len()) {
-            Some(header) => header,
-            None => return None,
+    }
+
+    // Like build, but datagrams longer than the MTU are fragmented instead of refused
+    pub fn build_fragments(&self) -> Result<Vec<Packet>, BuildError> {
+
+        let packet = self.build_with_mtu(None)?;
+
+        match self.mtu {
+            Some(mtu) => packet.fragment(mtu),
+            None => Ok(vec![packet]),
+        }
+
+    }
+
+    fn build_with_mtu(&self, mtu: Option<usize>) -> Result<Packet, BuildError> {
+
+        let (mut tcp_header, network_header) = match &self.ipv6_header_builder {
+            Some(ipv6_header_builder) => {
+                let tcp_header = self.tcp_header_builder.build_with(&ipv6_header_builder.get_pseudo_header(), &self.bytes[..])?;
+                let ipv6_header = ipv6_header_builder.build(&tcp_header, self.bytes.len())?;
+                (tcp_header, NetworkHeader::Ipv6(ipv6_header))
+            },
+            None => {
+                let tcp_header = self.tcp_header_builder.build(&self.ip_header_builder, &self.bytes[..])?;
+                let ip_header = self.ip_header_builder.build(&tcp_header, self.bytes.len())?;
+                (tcp_header, NetworkHeader::Ipv4(ip_header))
+            },
         };
 
-        let eth_header = [0x00, 0x00, 0x08, 0x00];
+        let header_length = network_header.get_length();
+        let total_length = header_length + tcp_header.data_offset as usize + self.bytes.len();
+
+        if total_length > self.max_length {
+            // Cancel if packet too big
+            return Err(BuildError::PacketTooLarge { max: self.max_length, actual: total_length });
+        }
+
+        let mut link_layer = self.build_link_layer(network_header.get_ether_type());
+
+        if matches!(link_layer, LinkLayer::Pppoe(_, _)) && total_length > 65533 {
+            // Cancel if packet and PPP protocol overflow the session length field
+            return Err(BuildError::PacketTooLarge { max: 65533, actual: total_length });
+        }
+
+        link_layer.set_payload_length(total_length);
+
+        if let Some(mtu) = mtu {
+
+            // Super-packets are cut to gso_size data bytes before they reach the link
+            let segment_length = match &link_layer {
+                LinkLayer::VirtioNet(virtio_net_header) if virtio_net_header.is_tcp_gso() => {
+                    total_length.min(header_length + tcp_header.data_offset as usize + virtio_net_header.gso_size as usize)
+                },
+                _ => total_length,
+            };
+
+            if segment_length > mtu {
+                // Cancel if packet does not fit the link
+                return Err(BuildError::ExceedsMtu { mtu, actual: segment_length });
+            }
+
+        }
+
+        if let (LinkLayer::VirtioNet(virtio_net_header), Some(pseudo_header)) = (&mut link_layer, network_header.get_pseudo_header()) {
+
+            virtio_net_header.header_length = (header_length + tcp_header.data_offset as usize) as u16;
+
+            if virtio_net_header.needs_checksum() {
+                // Leave the pseudo header sum for the device to finish
+                let checksum = pseudo_header.calculate_partial_checksum(IP_PROTOCOL_TCP, tcp_header.data_offset as usize + self.bytes.len());
+                virtio_net_header.checksum_start = header_length as u16;
+                virtio_net_header.checksum_offset = 16;
+                tcp_header.checksum = checksum;
+                tcp_header.bytes[16 .. 18].copy_from_slice(&checksum.to_be_bytes());
+            }
+
+        }
 
         let mut bytes = Vec::new();
-        bytes.extend_from_slice(&eth_header);
-        bytes.extend_from_slice(ip_header.get_bytes());
+        link_layer.write(&mut bytes);
+        match &network_header {
+            NetworkHeader::Ipv4(ip_header) => bytes.extend_from_slice(ip_header.get_bytes()),
+            NetworkHeader::Ipv6(ipv6_header) => bytes.extend_from_slice(ipv6_header.get_bytes()),
+            NetworkHeader::Arp(_) | NetworkHeader::PppoeDiscovery(_) => unreachable!(),
+        }
         bytes.extend_from_slice(tcp_header.get_bytes());
         bytes.extend_from_slice(&self.bytes);
 
-        Some(
+        Ok(
+            Packet {
+                bytes,
+                link_layer,
+                network_header,
+                tcp_header: Some(tcp_header),
+            }
+        )
+
+    }
+
+    // Frames arp_packet instead of the IP and TCP headers
+    pub fn build_arp(&self, arp_packet: &ArpPacket) -> Result<Packet, BuildError> {
+
+        if let Some(mtu) = self.mtu {
+            if 28 > mtu {
+                // Cancel if packet does not fit the link
+                return Err(BuildError::ExceedsMtu { mtu, actual: 28 });
+            }
+        }
+
+        let link_layer = self.build_link_layer(0x0806);
+
+        let mut bytes = Vec::new();
+        link_layer.write(&mut bytes);
+        bytes.extend_from_slice(&arp_packet.get_bytes());
+
+        Ok(
+            Packet {
+                bytes,
+                link_layer,
+                network_header: NetworkHeader::Arp(*arp_packet),
+                tcp_header: None,
+            }
+        )
+
+    }
+
+    // Frames a PPPoE discovery packet instead of the IP and TCP headers
+    pub fn build_pppoe_discovery(&self, discovery: &PppoeDiscovery) -> Result<Packet, BuildError> {
+
+        let length = discovery.get_length();
+
+        if length > 6 + 65535 {
+            // Cancel if tags overflow the length field
+            return Err(BuildError::PacketTooLarge { max: 6 + 65535, actual: length });
+        }
+
+        if let Some(mtu) = self.mtu {
+            if length > mtu {
+                // Cancel if packet does not fit the link
+                return Err(BuildError::ExceedsMtu { mtu, actual: length });
+            }
+        }
+
+        let link_layer = self.build_link_layer(ETHER_TYPE_PPPOE_DISCOVERY);
+
+        let mut bytes = Vec::new();
+        link_layer.write(&mut bytes);
+        bytes.extend_from_slice(&discovery.get_bytes());
+
+        Ok(
             Packet {
                 bytes,
-                ip_header,
-                tcp_header,
+                link_layer,
+                network_header: NetworkHeader::PppoeDiscovery(discovery.clone()),
+                tcp_header: None,
             }
         )
 
     }
 
+    fn build_link_layer(&self, ether_type: u16) -> LinkLayer {
+
+        match self.framing {
+            Framing::NoPacketInfo => LinkLayer::None,
+            Framing::PacketInfo | Framing::Auto => LinkLayer::Tun(TunHeader::new(ether_type)),
+            Framing::Ethernet => match self.pppoe_header {
+                // ARP and discovery have no place in a PPP session
+                Some(mut pppoe_header) if ether_type != 0x0806 && ether_type != ETHER_TYPE_PPPOE_DISCOVERY => {
+                    pppoe_header.set_ether_type(ether_type);
+                    LinkLayer::Pppoe(self.ethernet_header_builder.build(ETHER_TYPE_PPPOE_SESSION), pppoe_header)
+                },
+                _ if !self.mpls_labels.is_empty() && ether_type != 0x0806 && ether_type != ETHER_TYPE_PPPOE_DISCOVERY => LinkLayer::Mpls {
+                    ethernet_header: self.ethernet_header_builder.build(ETHER_TYPE_MPLS_UNICAST),
+                    labels: MplsLabel::terminate_stack(&self.mpls_labels),
+                    ether_type,
+                },
+                _ => LinkLayer::Ethernet(self.ethernet_header_builder.build(ether_type)),
+            },
+            Framing::VirtioNet => LinkLayer::VirtioNet(self.virtio_net_header),
+            Framing::Sll => LinkLayer::Sll(SllHeader::new(ether_type)),
+            Framing::Sll2 => LinkLayer::Sll2(Sll2Header::new(ether_type)),
+        }
+
+    }
+
 }
@@ -0,0 +1,72 @@
+// Validation switches for parsers, bounds are always checked regardless of these
+
+use crate::link_layer::Framing;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationPolicy {
+
+    Strict,                         // Every check, for forwarding
+    Lenient,                        // Structure only, decodes packets with bad checksums or reserved bits
+    None,                           // Anything that can be sliced without panicking
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+
+    pub verify_ip_checksum: bool,
+    pub verify_tcp_checksum: bool,
+    pub check_version: bool,
+    pub check_reserved_bits: bool,  // IPv4 reserved flag and TCP reserved bits
+    pub check_total_length: bool,   // Total length must cover the header and fit the buffer
+    pub framing: Framing,
+
+}
+
+impl ParseOptions {
+
+    pub fn new(policy: ValidationPolicy) -> ParseOptions {
+
+        let strict = policy == ValidationPolicy::Strict;
+        let structural = policy != ValidationPolicy::None;
+
+        ParseOptions {
+            verify_ip_checksum: strict,
+            verify_tcp_checksum: strict,
+            check_version: structural,
+            check_reserved_bits: strict,
+            check_total_length: structural,
+            framing: Framing::PacketInfo,
+        }
+
+    }
+
+}
+
+impl Default for ParseOptions {
+
+    // Checks parse has always made
+    fn default() -> ParseOptions {
+
+        ParseOptions {
+            verify_ip_checksum: false,
+            verify_tcp_checksum: true,
+            check_version: false,
+            check_reserved_bits: false,
+            check_total_length: true,
+            framing: Framing::PacketInfo,
+        }
+
+    }
+
+}
+
+impl From<ValidationPolicy> for ParseOptions {
+
+    fn from(policy: ValidationPolicy) -> ParseOptions {
+
+        ParseOptions::new(policy)
+
+    }
+
+}
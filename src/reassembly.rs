@@ -0,0 +1,387 @@
+// IPv4 fragment reassembly
+// https://tools.ietf.org/html/rfc791#section-3.2
+// https://tools.ietf.org/html/rfc815
+// https://tools.ietf.org/html/rfc5722
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::error::ReassemblyError;
+use crate::headers::ip_header::IPHeader;
+use crate::link_layer::LinkLayer;
+use crate::packet::{NetworkHeader, Packet};
+use crate::parse_options::ParseOptions;
+
+// Fragments belong to the same datagram if all of these match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FragmentKey {
+
+    pub source_address: u32,
+    pub destination_address: u32,
+    pub protocol: u8,
+    pub identification: u16,
+
+}
+
+// What to do with a fragment covering data already held. Exact duplicates are always ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+
+    Discard,                        // Default, RFC 5722, drop the whole datagram
+    KeepFirst,                      // Bytes already held win
+    KeepLast,                       // Newer bytes overwrite
+
+}
+
+// Fragments of one datagram
+struct FragmentBuffer {
+
+    deadline: Instant,                          // Arrival of the first fragment plus timeout
+    data: Vec<u8>,                              // Datagram data up to the furthest end seen, holes zeroed
+    ranges: Vec<(usize, usize)>,                // Data held, sorted and merged, end exclusive
+    data_length: Option<usize>,                 // Known once the last fragment arrived
+    first: Option<(LinkLayer, Vec<u8>)>,        // Link layer and IP header of the fragment at offset 0
+    memory: usize,                              // Bytes allocated for data, holes included
+
+}
+
+pub struct Reassembler {
+
+    pub timeout: Duration,                      // Per datagram, counted from its first fragment
+    pub max_memory: usize,                      // Data buffers across all datagrams, holes included
+    pub overlap_policy: OverlapPolicy,
+    pub parse_options: ParseOptions,            // For reassembled datagrams, framing is taken from the first fragment
+    buffers: HashMap<FragmentKey, FragmentBuffer>,
+    deadlines: BTreeSet<(Instant, FragmentKey)>,    // Same datagrams as buffers, soonest to time out first
+    memory: usize,
+
+}
+
+impl FragmentKey {
+
+    pub fn new(ip_header: &IPHeader) -> FragmentKey {
+
+        FragmentKey {
+            source_address: ip_header.source_address,
+            destination_address: ip_header.destination_address,
+            protocol: ip_header.protocol,
+            identification: ip_header.identification,
+        }
+
+    }
+
+}
+
+impl FragmentBuffer {
+
+    fn new(deadline: Instant) -> FragmentBuffer {
+
+        FragmentBuffer {
+            deadline,
+            data: Vec::new(),
+            ranges: Vec::new(),
+            data_length: None,
+            first: None,
+            memory: 0,
+        }
+
+    }
+
+    fn add(&mut self, offset: usize, data: &[u8], last: bool, policy: OverlapPolicy) -> Result<(), ReassemblyError> {
+
+        let end = offset + data.len();
+        let held_end = self.ranges.last().map(|range| range.1).unwrap_or(0);
+
+        match self.data_length {
+            Some(data_length) if (last && end != data_length) || end > data_length => {
+                // Cancel if fragment does not agree with the last fragment
+                return Err(ReassemblyError::InvalidLength { expected: data_length, actual: end });
+            },
+            None if last && held_end > end => {
+                // Cancel if data was received past the end
+                return Err(ReassemblyError::InvalidLength { expected: held_end, actual: end });
+            },
+            _ => (),
+        }
+
+        if last {
+            self.data_length = Some(end);
+        }
+
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let overlaps = self.ranges.iter().any(|&(start, stop)| start < end && offset < stop);
+        let covered = self.ranges.iter().any(|&(start, stop)| start <= offset && end <= stop);
+
+        if covered && self.data[offset .. end] == *data {
+            // Retransmitted fragment
+            return Ok(());
+        }
+
+        if overlaps && policy == OverlapPolicy::Discard {
+            return Err(ReassemblyError::Overlap { offset, length: data.len() });
+        }
+
+        if self.data.len() < end {
+            self.data.reserve_exact(end - self.data.len());
+            self.data.resize(end, 0);
+            self.memory = self.data.len();
+        }
+
+        match policy {
+            OverlapPolicy::Discard | OverlapPolicy::KeepLast => self.data[offset .. end].copy_from_slice(data),
+            OverlapPolicy::KeepFirst => {
+                for (start, stop) in self.get_holes(offset, end) {
+                    self.data[start .. stop].copy_from_slice(&data[start - offset .. stop - offset]);
+                }
+            },
+        }
+
+        self.ranges.push((offset, end));
+        self.ranges.sort_unstable();
+
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(self.ranges.len());
+
+        for (start, stop) in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(range) if start <= range.1 => range.1 = range.1.max(stop),
+                _ => merged.push((start, stop)),
+            }
+        }
+
+        self.ranges = merged;
+
+        Ok(())
+
+    }
+
+    // Parts of offset to end not held yet
+    fn get_holes(&self, offset: usize, end: usize) -> Vec<(usize, usize)> {
+
+        let mut holes = Vec::new();
+        let mut position = offset;
+
+        for &(start, stop) in self.ranges.iter() {
+            if start >= end {
+                break;
+            }
+            if start > position {
+                holes.push((position, start));
+            }
+            position = position.max(stop);
+        }
+
+        if position < end {
+            holes.push((position, end));
+        }
+
+        holes
+
+    }
+
+    fn is_complete(&self) -> bool {
+
+        match self.data_length {
+            Some(data_length) => self.first.is_some() && (data_length == 0 || self.ranges == [(0, data_length)]),
+            None => false,
+        }
+
+    }
+
+    fn assemble(self, options: &ParseOptions) -> Result<Packet, ReassemblyError> {
+
+        let (mut link_layer, mut header) = self.first.expect("complete buffers hold the first fragment");
+        let total_length = header.len() + self.data.len();
+
+        if total_length > 65535 {
+            // Cancel if data does not fit behind the first fragment's header
+            return Err(ReassemblyError::PacketTooLarge { max: 65535, actual: total_length });
+        }
+
+        // Whole datagram, DF kept, MF and offset cleared
+        header[2 .. 4].copy_from_slice(&(total_length as u16).to_be_bytes());
+        header[6] &= 0b01000000;
+        header[7] = 0;
+
+        let header_checksum = IPHeader::calculate_checksum(&header);
+        header[10 .. 12].copy_from_slice(&header_checksum.to_be_bytes());
+
+        link_layer.set_payload_length(total_length);
+
+        let mut bytes = Vec::with_capacity(link_layer.get_length() + total_length);
+        link_layer.write(&mut bytes);
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&self.data);
+
+        let options = ParseOptions { framing: link_layer.get_framing(), ..*options };
+
+        Packet::parse_with(&bytes, &options).map_err(ReassemblyError::Parse)
+
+    }
+
+}
+
+impl Default for Reassembler {
+
+    fn default() -> Reassembler {
+
+        Reassembler::new()
+
+    }
+
+}
+
+impl Reassembler {
+
+    pub fn new() -> Reassembler {
+
+        Reassembler {
+            timeout: Duration::from_secs(30),
+            max_memory: 4 * 1024 * 1024,
+            overlap_policy: OverlapPolicy::Discard,
+            parse_options: ParseOptions::default(),
+            buffers: HashMap::new(),
+            deadlines: BTreeSet::new(),
+            memory: 0,
+        }
+
+    }
+
+    // Whole datagrams come straight back. Fragments are held until their datagram is complete,
+    // which is then returned parsed. Errors drop every fragment of the datagram
+    pub fn insert(&mut self, packet: Packet, now: Instant) -> Result<Option<Packet>, ReassemblyError> {
+
+        self.expire(now);
+
+        let is_fragment = matches!(&packet.network_header, NetworkHeader::Ipv4(ip_header) if ip_header.is_fragment());
+
+        if !is_fragment {
+            return Ok(Some(packet));
+        }
+
+        let NetworkHeader::Ipv4(ip_header) = &packet.network_header else { unreachable!() };
+
+        let key = FragmentKey::new(ip_header);
+        let offset = ip_header.fragment_offset as usize * 8;
+        // Total length bounds the data, link layer padding may follow
+        let ip_offset = packet.get_ip_offset();
+        let data_end = packet.bytes.len().min(ip_offset + ip_header.total_length as usize);
+        let data = &packet.bytes[(ip_offset + ip_header.header_length as usize).min(data_end) .. data_end];
+
+        if offset + data.len() > 65515 {
+            // Cancel if data ends past what the smallest header leaves room for
+            self.discard(&key);
+            return Err(ReassemblyError::PacketTooLarge { max: 65535, actual: 20 + offset + data.len() });
+        }
+
+        if ip_header.more_fragments && data.len() % 8 != 0 {
+            // Only the last fragment may end off the 8 byte grid
+            self.discard(&key);
+            return Err(ReassemblyError::InvalidLength { expected: data.len().next_multiple_of(8), actual: data.len() });
+        }
+
+        // A fragment far into the datagram allocates everything in front of it
+        let allocated = self.buffers.get(&key).map(|buffer| buffer.memory).unwrap_or(0);
+        self.reserve(&key, (offset + data.len()).saturating_sub(allocated))?;
+
+        let deadline = now + self.timeout;
+        let deadlines = &mut self.deadlines;
+        let buffer = self.buffers.entry(key).or_insert_with(|| {
+            deadlines.insert((deadline, key));
+            FragmentBuffer::new(deadline)
+        });
+
+        let held = buffer.memory;
+        let result = buffer.add(offset, data, !ip_header.more_fragments, self.overlap_policy);
+
+        // Account for any growth before a failed datagram is discarded
+        self.memory += buffer.memory - held;
+
+        if let Err(error) = result {
+            self.discard(&key);
+            return Err(error);
+        }
+
+        if offset == 0 && buffer.first.is_none() {
+            buffer.first = Some((packet.link_layer.clone(), ip_header.get_bytes().to_vec()));
+        }
+
+        if !buffer.is_complete() {
+            return Ok(None);
+        }
+
+        let buffer = self.buffers.remove(&key).expect("buffer was just used");
+        self.deadlines.remove(&(buffer.deadline, key));
+        self.memory -= buffer.memory;
+
+        buffer.assemble(&self.parse_options).map(Some)
+
+    }
+
+    // Drops datagrams whose timeout has passed, returns how many
+    pub fn expire(&mut self, now: Instant) -> usize {
+
+        let mut expired = 0;
+
+        while let Some(&(deadline, key)) = self.deadlines.iter().next() {
+            if deadline > now {
+                break;
+            }
+            self.discard(&key);
+            expired += 1;
+        }
+
+        expired
+
+    }
+
+    // Datagrams waiting for fragments
+    pub fn get_pending(&self) -> usize {
+
+        self.buffers.len()
+
+    }
+
+    pub fn get_memory(&self) -> usize {
+
+        self.memory
+
+    }
+
+    // Evicts the datagrams closest to timing out until length more bytes fit
+    fn reserve(&mut self, key: &FragmentKey, length: usize) -> Result<(), ReassemblyError> {
+
+        while self.memory + length > self.max_memory {
+
+            // Skips at most the datagram being added to
+            let oldest = self.deadlines.iter()
+                .map(|(_, other)| *other)
+                .find(|other| other != key);
+
+            match oldest {
+                Some(oldest) => self.discard(&oldest),
+                None => {
+                    let actual = self.memory + length;
+                    self.discard(key);
+                    return Err(ReassemblyError::MemoryExhausted { limit: self.max_memory, actual });
+                },
+            }
+
+        }
+
+        Ok(())
+
+    }
+
+    fn discard(&mut self, key: &FragmentKey) {
+
+        if let Some(buffer) = self.buffers.remove(key) {
+            self.deadlines.remove(&(buffer.deadline, *key));
+            self.memory -= buffer.memory;
+        }
+
+    }
+
+}